@@ -1,5 +1,7 @@
 pub mod magics;
+#[cfg(attack_tables)]
 pub mod attack_tables;
+#[cfg(attack_tables)]
 pub mod attack_bb;
 
 use crate::types::bitboard::*;
@@ -7,7 +9,7 @@ use crate::types::square::*;
 use crate::types::piece::*;
 
 const fn is_square_ok(s: Square) -> bool {
-    s.0 >= Square::A1.0 && s.0 <= Square::H8.0
+    s.0 <= Square::H8.0
 }
 
 const fn shift_square(s: Square, step: i32) -> Square {
@@ -1,8 +1,9 @@
 use super::{attacks_bb};
-use crate::attacks::{sliding_attacks};
-use crate::types::square::Square;
+use crate::attacks::{sliding_attacks, square_bb};
+use crate::attacks::magics::{BISHOP_MAGICS, ROOK_MAGICS};
+use crate::types::square::{Square, VALID_SQUARES};
 use crate::types::piece::{ROOK, BISHOP};
-use crate::types::bitboard::{EMPTY_BB};
+use crate::types::bitboard::{Bitboard, EMPTY_BB};
 
 #[test]
 fn magic_attacks_match_sliding_attacks() {
@@ -16,3 +17,29 @@ fn magic_attacks_match_sliding_attacks() {
     }
     
 }
+
+// Every entry of the generated tables, whether indexed with PEXT or with the
+// magics, must match the attacks computed by walking the board.
+#[test]
+fn slider_tables_match_sliding_attacks() {
+
+    for (magics, pt) in [(BISHOP_MAGICS, BISHOP), (ROOK_MAGICS, ROOK)] {
+        for s in VALID_SQUARES {
+            let mask = magics[s.0 as usize].mask;
+            let outside = !mask & !square_bb(s);
+            let mut occ = EMPTY_BB;
+
+            // Enumerate all the subsets of the mask (Carry-Rippler trick),
+            // with and without the squares outside the mask occupied.
+            loop {
+                assert_eq!(attacks_bb(pt, s, occ), sliding_attacks(pt, s, occ));
+                assert_eq!(attacks_bb(pt, s, occ | outside), sliding_attacks(pt, s, occ | outside));
+
+                occ = Bitboard(occ.0.wrapping_sub(mask.0) & mask.0);
+                if occ == EMPTY_BB {
+                    break;
+                }
+            }
+        }
+    }
+}
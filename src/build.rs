@@ -1,3 +1,7 @@
+// The modules are shared with the engine, which uses much more of them and
+// where they are linted.
+#![allow(dead_code, unused_imports, clippy::all)]
+
 pub mod types;
 pub mod attacks;
 
@@ -12,31 +16,35 @@ use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 
-// A few comments on the different scenarios this file handles (and does not) with the corresponding compile commands
-// Native compile for own machine : set RUSTFLAGS=-C target-cpu=native; cargo run --release (works)
-// Host compile for target machine: set RUSTFLAGS=-C target-cpu=<your-target-machine-cpu>; cargo rustc --release --bin scam --target <your_target>
-// In the case that the Host does not have BMI2, while the target-cpu wants BMI2 instructions, this build script will fail.
-// Due to https://github.com/rust-lang/cargo/issues/4423 (build.rs can't be given any build flags), we sadly can not detect
-// whether the host has the bmi2 instruction set or not. For now, we will just assume it has.
-// For cross-compiling purposes, we extract the target-feature from the env var CARGO_CFG_TARGET_FEATURE instead of
-// using #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))] since that is the config for the host, and has nothing to do
-// with the target in case of cross compilation. Additionally, if --target is supplied during compilation,
-// #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))] will always evaluate to false due to above issue.
+// The build script generates the slider attack tables, and the line and
+// between bitboards, in $OUT_DIR/attack_tables.rs, which is included by
+// attacks::attack_tables. The slider tables are indexed with PEXT when the
+// target has BMI2 (e.g. RUSTFLAGS="-C target-cpu=native"), and with the
+// magics otherwise.
+// The target features are read from CARGO_CFG_TARGET_FEATURE, since a
+// #[cfg(target_feature = "bmi2")] in this file would be the one of the host
+// running the build script. For the same reason PEXT is emulated here, so
+// that the tables can be built on a host without BMI2.
 pub fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
     let magic_path = Path::new(&out_dir).join("attack_tables.rs");
     let mut file = File::create(magic_path).unwrap();
 
-    let has_bmi2 = env::var("CARGO_CFG_TARGET_FEATURE").map_or(false, |x| x.contains("bmi2"));
+    let has_bmi2 = env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|x| x == "x86_64")
+        && env::var("CARGO_CFG_TARGET_FEATURE").is_ok_and(|x| x.split(',').any(|f| f == "bmi2"));
     if has_bmi2 {
         writeln!(file, "//Tables for BMI2").unwrap();
     } else {
         writeln!(file, "//Tables for Magics").unwrap();
     }
+    writeln!(file, "\nuse crate::types::bitboard::{{Bitboard}};").unwrap();
+    writeln!(file, "use crate::types::square::{{SQUARE_NB}};\n").unwrap();
+
     let attacks = init_attacks(has_bmi2);
     write!(
         file,
-        "#[rustfmt::skip]\n pub static ATTACKS: [u64; 107648] = {};\n",
+        "#[rustfmt::skip]\n pub static ATTACKS: [u64; {}] = {};\n",
+        attacks.len(),
         print_arr1d(&attacks, false)
     )
     .unwrap();
@@ -56,8 +64,15 @@ pub fn main() {
     )
     .unwrap();
 
-    //println!("cargo:rerun-if-changed=build.rs");
+    // The engine compiles the attack_bb module only with the generated
+    // tables, the build script itself being compiled without them.
+    println!("cargo:rustc-cfg=attack_tables");
 
+    println!("cargo:rerun-if-changed=src/build.rs");
+    println!("cargo:rerun-if-changed=src/attacks.rs");
+    println!("cargo:rerun-if-changed=src/attacks/magics.rs");
+    println!("cargo:rerun-if-changed=src/types");
+    println!("cargo:rerun-if-changed=src/types.rs");
 }
 
 pub fn print_arr2d(arr: &[Vec<Bitboard>], bb: bool) -> String {
@@ -85,11 +100,25 @@ pub fn print_arr1d(arr: &[Bitboard], bb: bool) -> String {
 }
 
 
+// pext() is a software version of the BMI2 instruction: the bits of x
+// selected by the mask are packed into the low bits of the result.
+fn pext(x: u64, mut mask: u64) -> u64 {
+    let mut res = 0;
+    let mut bit = 1;
+    while mask != 0 {
+        if x & mask & mask.wrapping_neg() != 0 {
+            res |= bit;
+        }
+        bit <<= 1;
+        mask &= mask - 1;
+    }
+    res
+}
+
 impl Magic {
     pub fn build_index(self, occ: Bitboard, has_bmi2: bool) -> usize {
         if has_bmi2 {
-            use std::arch::x86_64::_pext_u64;
-            self.offset + unsafe { _pext_u64(occ.0, self.mask.0) } as usize
+            self.offset + pext(occ.0, self.mask.0) as usize
         } else {
             self.offset + (((occ & self.mask).0).wrapping_mul(self.magic) >> self.shift) as usize
         }
@@ -97,7 +126,8 @@ impl Magic {
 }
 
 pub fn init_attacks(has_bmi2: bool) -> Vec<Bitboard> {
-    let mut res = vec![EMPTY_BB; 107648];
+    let size = ROOK_MAGICS[SQUARE_NB - 1].offset + (1 << popcount(ROOK_MAGICS[SQUARE_NB - 1].mask));
+    let mut res = vec![EMPTY_BB; size];
     for (magics, pt) in [(BISHOP_MAGICS, BISHOP), (ROOK_MAGICS, ROOK)].iter() {
         for (sq, magic) in magics.iter().enumerate() {
             let mut occ = EMPTY_BB;
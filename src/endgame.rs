@@ -0,0 +1,393 @@
+use crate::attacks::attack_bb::*;
+use crate::position::Position;
+use crate::tablebase::Tablebases;
+use crate::types::bitboard::*;
+use crate::types::piece::*;
+use crate::types::score::*;
+use crate::types::square::*;
+use crate::zobrist::{Key, KEY_ZERO, ZOBRIST};
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Scale factors applied to the endgame part of the evaluation, out of 64
+pub const SCALE_FACTOR_DRAW: i32 = 0;
+pub const SCALE_FACTOR_NORMAL: i32 = 64;
+
+// Endgame evaluation functions return the value from the point of view of
+// the side to move, scaling functions a scale factor or None when the
+// configuration is not drawish.
+type EvalFn = fn(&Position, Color) -> Value;
+type ScaleFn = fn(&Position, Color) -> Option<i32>;
+
+// Endgames maps the material keys of the known endgames, with either side
+// being the strong one, to their function and strong side.
+pub struct Endgames {
+    values: HashMap<Key, (EvalFn, Color)>,
+    scales: HashMap<Key, (ScaleFn, Color)>,
+}
+
+impl Endgames {
+
+    fn new() -> Endgames {
+        let mut endgames = Endgames { values: HashMap::new(), scales: HashMap::new() };
+
+        for (code, f) in [("KBNK", evaluate_kbnk as EvalFn), ("KPK", evaluate_kpk), ("KRKP", evaluate_krkp)] {
+            for strong in [WHITE, BLACK] {
+                endgames.values.insert(material_key(code, strong), (f, strong));
+            }
+        }
+
+        for (code, f) in [("KRPKR", scale_krpkr as ScaleFn), ("KBPKB", scale_kbpkb)] {
+            for strong in [WHITE, BLACK] {
+                endgames.scales.insert(material_key(code, strong), (f, strong));
+            }
+        }
+
+        endgames
+    }
+}
+
+// material_key() computes the material key of an endgame code like "KBNK",
+// the pieces up to the second king belonging to the strong side.
+fn material_key(code: &str, strong: Color) -> Key {
+    let second_king = code.rfind('K').unwrap();
+    let mut counts = [0u32; PIECE_NB];
+    let mut key = KEY_ZERO;
+
+    for (idx, c) in code.char_indices() {
+        let color = if idx < second_king { strong } else { !strong };
+        let pt = PieceType(PIECE_TO_CHAR.iter().position(|&pc| pc == c).unwrap() as u32);
+        let pc = Piece::make(color, pt);
+        key ^= ZOBRIST.psq[pc][Square(counts[pc.0 as usize])];
+        counts[pc.0 as usize] += 1;
+    }
+    key
+}
+
+fn endgames() -> &'static Endgames {
+    static ENDGAMES: OnceLock<Endgames> = OnceLock::new();
+    ENDGAMES.get_or_init(Endgames::new)
+}
+
+// evaluate() returns the value of the position if there is a dedicated
+// evaluation function for its material: the registered endgames, and KXK
+// for a lone king against enough material to mate.
+pub fn evaluate(pos: &Position) -> Option<Value> {
+
+    if let Some(&(f, strong)) = endgames().values.get(&pos.material_key()) {
+        return Some(f(pos, strong));
+    }
+
+    for strong in [WHITE, BLACK] {
+        if pos.pieces_c(!strong) == pos.pieces_cp(!strong, KING)
+            && pos.non_pawn_material(strong) >= Value::ROOK_MG {
+            return Some(evaluate_kxk(pos, strong));
+        }
+    }
+
+    None
+}
+
+// scale_factor() returns the scale factor of the endgame part of the
+// evaluation, 'strong' being the side ahead in the endgame score.
+pub fn scale_factor(pos: &Position, strong: Color) -> i32 {
+
+    if let Some(&(f, s)) = endgames().scales.get(&pos.material_key()) {
+        if let Some(sf) = f(pos, s) {
+            return sf;
+        }
+    }
+
+    let weak = !strong;
+    let npm_strong = pos.non_pawn_material(strong);
+    let npm_weak = pos.non_pawn_material(weak);
+
+    // Without pawns, a small material advantage is not enough to win
+    if pos.count(strong, PAWN) == 0 && npm_strong - npm_weak <= Value::BISHOP_MG {
+        return if npm_strong < Value::ROOK_MG {
+            SCALE_FACTOR_DRAW
+        } else if npm_weak <= Value::BISHOP_MG {
+            4
+        } else {
+            14
+        };
+    }
+
+    // Bishop and rook pawns of the wrong color
+    if npm_strong == Value::BISHOP_MG && pos.count(strong, BISHOP) == 1 && pos.count(strong, PAWN) > 0 {
+        if let Some(sf) = scale_kbpsk(pos, strong) {
+            return sf;
+        }
+    }
+
+    // Opposite colored bishops
+    if pos.count(WHITE, BISHOP) == 1
+        && pos.count(BLACK, BISHOP) == 1
+        && opposite_colors(pos.square(WHITE, BISHOP), pos.square(BLACK, BISHOP)) {
+        return if npm_strong == Value::BISHOP_MG && npm_weak == Value::BISHOP_MG { 22 } else { 46 };
+    }
+
+    SCALE_FACTOR_NORMAL
+}
+
+fn edge_distance(s: Square) -> (i32, i32) {
+    let f = s.file().0 as i32;
+    let r = s.rank().0 as i32;
+    (f.min(7 - f), r.min(7 - r))
+}
+
+// Drive a piece towards the edge of the board, used in KX vs K
+fn push_to_edge(s: Square) -> Value {
+    let (fd, rd) = edge_distance(s);
+    Value(90 - (7 * fd * fd / 2 + 7 * rd * rd / 2))
+}
+
+// Drive a piece towards the a1-h8 diagonal corners, used in KBN vs K
+fn push_to_corner(s: Square) -> Value {
+    Value((7 - s.rank().0 as i32 - s.file().0 as i32).abs())
+}
+
+// Drive two pieces close together
+fn push_close(s1: Square, s2: Square) -> Value {
+    Value(140 - 20 * square_distance(s1, s2) as i32)
+}
+
+fn distance(s1: Square, s2: Square) -> i32 {
+    square_distance(s1, s2) as i32
+}
+
+fn from_side_to_move(pos: &Position, strong: Color, value: Value) -> Value {
+    if pos.side_to_move() == strong { value } else { -value }
+}
+
+fn is_stalemate(pos: &Position) -> bool {
+    pos.checkers() == 0 && pos.legal_moves().is_empty()
+}
+
+// Mate with KX vs K. This function is used to evaluate positions with king
+// and plenty of material vs a lone king. It simply gives the attacking side
+// a bonus for driving the defending king towards the edge of the board, and
+// for keeping the distance between the two kings small.
+fn evaluate_kxk(pos: &Position, strong: Color) -> Value {
+
+    let weak = !strong;
+
+    // Stalemate detection with lone king
+    if pos.side_to_move() == weak && is_stalemate(pos) {
+        return Value::DRAW;
+    }
+
+    let winner_ksq = pos.square(strong, KING);
+    let loser_ksq = pos.square(weak, KING);
+
+    let mut result = pos.non_pawn_material(strong)
+        + Value::PAWN_EG * pos.count(strong, PAWN)
+        + push_to_edge(loser_ksq)
+        + push_close(winner_ksq, loser_ksq);
+
+    if pos.count(strong, QUEEN) > 0
+        || pos.count(strong, ROOK) > 0
+        || (pos.count(strong, BISHOP) > 0 && pos.count(strong, KNIGHT) > 0)
+        || (pos.pieces_cp(strong, BISHOP) & DARK_SQUARES != 0 && pos.pieces_cp(strong, BISHOP) & !DARK_SQUARES != 0) {
+        result += Value::KNOWN_WIN;
+    }
+
+    from_side_to_move(pos, strong, result)
+}
+
+// Mate with KBN vs K. This is similar to KX vs K, but we have to drive the
+// defending king towards a corner square that our bishop attacks.
+fn evaluate_kbnk(pos: &Position, strong: Color) -> Value {
+
+    let winner_ksq = pos.square(strong, KING);
+    let loser_ksq = pos.square(!strong, KING);
+    let bishop_sq = pos.square(strong, BISHOP);
+
+    // If our bishop does not attack A1/H8, we flip the enemy king square
+    // to drive to opposite corners (A8/H1). The kings are still pushed
+    // close together on the real squares.
+    let corner_sq = if opposite_colors(bishop_sq, Square::A1) {
+        Square(loser_ksq.0 ^ 7)
+    } else {
+        loser_ksq
+    };
+
+    let result = Value::KNOWN_WIN + Value(3520)
+        + push_close(winner_ksq, loser_ksq)
+        + Value(420) * push_to_corner(corner_sq).0;
+
+    from_side_to_move(pos, strong, result)
+}
+
+fn kpk_bitbase() -> &'static Tablebases {
+    static KPK: OnceLock<Tablebases> = OnceLock::new();
+    KPK.get_or_init(|| {
+        let mut tb = Tablebases::new();
+        tb.generate("KPvK").unwrap();
+        tb
+    })
+}
+
+// KP vs K. This endgame is evaluated with the help of a bitbase, the KPK
+// table generated once by the retrograde tablebase generator.
+fn evaluate_kpk(pos: &Position, strong: Color) -> Value {
+
+    match kpk_bitbase().probe(pos) {
+        Some(v) if v != 0 => {
+            let psq = pos.square(strong, PAWN);
+            let result = Value::KNOWN_WIN + Value::PAWN_EG + Value(psq.relative_rank(strong).0 as i32);
+            from_side_to_move(pos, strong, result)
+        }
+        _ => Value::DRAW,
+    }
+}
+
+// KR vs KP. This is a somewhat tricky endgame to evaluate precisely without
+// a bitbase. The function below returns drawish scores when the pawn is
+// far advanced with support of the king, while the attacking king is far
+// away.
+fn evaluate_krkp(pos: &Position, strong: Color) -> Value {
+
+    let weak = !strong;
+    let wksq = relative_square(strong, pos.square(strong, KING));
+    let bksq = relative_square(strong, pos.square(weak, KING));
+    let rsq = relative_square(strong, pos.square(strong, ROOK));
+    let psq = relative_square(strong, pos.square(weak, PAWN));
+    let queening_sq = Square::make(psq.file(), RANK_1);
+    let below = |s: Square| Square(s.0 - 8);
+
+    let result = if forward_file_bb(WHITE, wksq) & psq != 0 {
+        // If the stronger side's king is in front of the pawn, it's a win
+        Value::ROOK_EG - distance(wksq, psq)
+    } else if distance(bksq, psq) >= 3 + (pos.side_to_move() == weak) as i32
+        && distance(bksq, rsq) >= 3 {
+        // If the weaker side's king is too far from the pawn and the rook,
+        // it's a win.
+        Value::ROOK_EG - distance(wksq, psq)
+    } else if bksq.rank() <= RANK_3
+        && distance(bksq, queening_sq) == 1
+        && wksq.rank() >= RANK_4
+        && distance(wksq, psq) > 2 + (pos.side_to_move() == strong) as i32 {
+        // If the pawn is far advanced and supported by the defending king,
+        // the position is drawish.
+        Value(80) - 8 * distance(wksq, psq)
+    } else {
+        Value(200) - 8 * (distance(wksq, below(psq))
+            - distance(bksq, below(psq))
+            - distance(psq, queening_sq))
+    };
+
+    from_side_to_move(pos, strong, result)
+}
+
+// KB and rook pawns vs K, with the bishop not controlling the queening
+// square and the defending king on it or next to it, is a draw.
+fn scale_kbpsk(pos: &Position, strong: Color) -> Option<i32> {
+
+    let pawns = pos.pieces_cp(strong, PAWN);
+    let pawns_file = lsb(pawns).file();
+
+    if (pawns_file == FILE_A || pawns_file == FILE_H) && pawns & !file_bb(pawns_file) == 0 {
+        let queening_sq = relative_square(strong, Square::make(pawns_file, RANK_8));
+        if opposite_colors(queening_sq, pos.square(strong, BISHOP))
+            && distance(queening_sq, pos.square(!strong, KING)) <= 1 {
+            return Some(SCALE_FACTOR_DRAW);
+        }
+    }
+    None
+}
+
+// KRP vs KR. When the defending king blocks the pawn on its file, the
+// ending is a likely draw.
+fn scale_krpkr(pos: &Position, strong: Color) -> Option<i32> {
+
+    let psq = pos.square(strong, PAWN);
+    let weak_ksq = pos.square(!strong, KING);
+
+    if forward_file_bb(strong, psq) & weak_ksq != 0 {
+        Some(if psq.relative_rank(strong) <= RANK_5 { 8 } else { 24 })
+    } else {
+        None
+    }
+}
+
+// KBP vs KB. With opposite colored bishops, or the defending king in front
+// of the pawn on a square the attacking bishop does not control, it is a
+// draw.
+fn scale_kbpkb(pos: &Position, strong: Color) -> Option<i32> {
+
+    let psq = pos.square(strong, PAWN);
+    let strong_bsq = pos.square(strong, BISHOP);
+    let weak_bsq = pos.square(!strong, BISHOP);
+    let weak_ksq = pos.square(!strong, KING);
+
+    if forward_file_bb(strong, psq) & weak_ksq != 0
+        && (opposite_colors(weak_ksq, strong_bsq) || psq.relative_rank(strong) <= RANK_5) {
+        return Some(SCALE_FACTOR_DRAW);
+    }
+
+    if opposite_colors(strong_bsq, weak_bsq) {
+        return Some(SCALE_FACTOR_DRAW);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod endgame_test {
+
+    use super::*;
+    use crate::evaluate;
+    use crate::types::r#move::Move;
+
+    fn eval_fen(fen: &str) -> Value {
+        let mut pos = Position::new();
+        pos.set(fen, false);
+        evaluate::evaluate(&pos)
+    }
+
+    #[test]
+    fn material_key_is_incremental() {
+        let mut pos = Position::new();
+        pos.set("r3k3/1P6/8/8/8/8/8/4K3 w q - 0 1", false);
+
+        for m in ["b7a8q", "e8d7", "a8a7"] {
+            let m = Move::from_uci(&pos, m).unwrap();
+            pos.do_move(m);
+            assert_eq!(pos.material_key(), pos.compute_material_key());
+        }
+
+        let mut kqk = Position::new();
+        kqk.set("8/Q7/8/8/3k4/8/8/4K3 b - - 0 1", false);
+        assert_eq!(pos.material_key(), kqk.material_key());
+    }
+
+    #[test]
+    fn known_endgames() {
+        // KXK and KBNK are known wins, the loser pushed to the right corner
+        assert!(eval_fen("8/8/8/3k4/8/8/8/R3K3 w - - 0 1") > Value::KNOWN_WIN);
+        assert!(eval_fen("8/8/8/3k4/8/8/8/R3K3 b - - 0 1") < -Value::KNOWN_WIN);
+        assert!(eval_fen("7k/8/5K2/8/8/8/8/B1N5 w - - 0 1") > eval_fen("k7/8/2K5/8/8/8/8/B1N5 w - - 0 1"));
+
+        // With a light-squared bishop, the winning king is drawn to the real
+        // loser king, not to its mirror image
+        assert!(eval_fen("7k/5K2/8/8/8/8/8/2N2B2 w - - 0 1") > eval_fen("7k/1K6/8/8/8/8/8/2N2B2 w - - 0 1"));
+
+        // KPK from the bitbase
+        assert_eq!(eval_fen("k7/8/8/8/8/8/P7/K7 w - - 0 1"), Value::DRAW);
+        assert!(eval_fen("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1") < -Value::KNOWN_WIN);
+
+        // KRKP: far advanced pawn supported by its king is drawish
+        assert!(eval_fen("8/8/8/8/8/2k5/2p5/K6R w - - 0 1") < eval_fen("8/8/8/8/8/2p1k3/8/2K4R w - - 0 1"));
+    }
+
+    #[test]
+    fn drawish_endgames_are_scaled() {
+        // KR vs KR, KB vs K, opposite colored bishops and wrong rook pawn bishop
+        assert!(eval_fen("8/8/3k4/8/8/3K4/r7/7R w - - 0 1").0.abs() < 50);
+        assert!(eval_fen("8/8/3k4/8/8/3K4/8/7B w - - 0 1").0.abs() < 50);
+        assert!(eval_fen("8/5b2/3k4/8/1P6/3K4/P7/2B5 w - - 0 1") < eval_fen("8/4b3/3k4/8/1P6/3K4/P7/2B5 w - - 0 1"));
+        assert!(eval_fen("k7/8/8/P7/8/8/8/2B3K1 w - - 0 1").0.abs() < 50);
+    }
+}
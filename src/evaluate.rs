@@ -1,9 +1,31 @@
-use crate::types::piece::{WHITE, BLACK, PAWN, KNIGHT, BISHOP, ROOK, QUEEN};
-use crate::types::bitboard::EMPTY_BB;
-use crate::types::r#move::Move;
-use crate::types::score::{Value, Phase, MAX_MOVES};
+use crate::types::piece::{Color, Piece, WHITE, BLACK, PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING};
+use crate::types::bitboard::{EMPTY_BB, DARK_SQUARES, CENTER, popcount, more_than_one};
+use crate::attacks::attack_bb::square_distance;
+use crate::zobrist::CHECKS_NB;
+use crate::types::score::{Value, Phase, Score, MG, EG, piece_value};
 use crate::position::Position;
-use crate::movegen::{ExtMove, generate_legal};
+use crate::variant::Variant;
+use crate::endgame;
+
+// Bonus for a piece in hand on top of its material value, since it can be
+// dropped on almost any square.
+const IN_HAND_BONUS: [Score; 6] = [
+    Score::ZERO,
+    Score::make(Value(30), Value(20)), Score::make(Value(50), Value(20)), Score::make(Value(30), Value(20)),
+    Score::make(Value(20), Value(20)), Score::make(Value(40), Value(20)),
+];
+
+// Bonus by the number of checks given in three-check. The third check wins.
+const CHECKS_GIVEN_BONUS: [Score; CHECKS_NB + 1] = [
+    Score::ZERO, Score::make(Value(150), Value(100)), Score::make(Value(450), Value(300)), Score::ZERO,
+];
+
+// Bonus in King of the Hill by the distance of the king to the center
+const HILL_BONUS: [Score; 8] = [
+    Score::ZERO, Score::make(Value(120), Value(200)), Score::make(Value(50), Value(90)),
+    Score::make(Value(20), Value(40)), Score::make(Value(5), Value(10)),
+    Score::ZERO, Score::ZERO, Score::ZERO,
+];
 
 // pub const MAX_VALUE_MG: Value = max_value_mg();
 
@@ -13,14 +35,48 @@ use crate::movegen::{ExtMove, generate_legal};
 
 pub fn evaluate(pos: &Position) -> Value {
 
-    let phase = Phase::PAWN * (pos.count(WHITE, PAWN) + pos.count(BLACK, PAWN)) +
-                Phase::KNIGHT * (pos.count(WHITE, KNIGHT) + pos.count(BLACK, KNIGHT)) + 
-                Phase::BISHOP * (pos.count(WHITE, BISHOP) + pos.count(BLACK, BISHOP)) +
-                Phase::ROOK * (pos.count(WHITE, ROOK) + pos.count(BLACK, ROOK)) +
-                Phase::QUEEN * (pos.count(WHITE, QUEEN) + pos.count(BLACK, QUEEN));
+    // The side with fewer pieces left is closer to winning in antichess
+    if pos.variant() == Variant::Antichess {
+        let value = Value::PAWN_MG * (popcount(pos.pieces_c(BLACK)) as i32 - popcount(pos.pieces_c(WHITE)) as i32);
+        return if pos.side_to_move() == WHITE { value } else { -value };
+    }
+
+    if pos.variant().is_chess() {
+        if let Some(value) = endgame::evaluate(pos) {
+            return value;
+        }
+    }
+
+    // Pieces in hand still count for the game phase
+    let count = |pt| pos.count(WHITE, pt) + pos.count(BLACK, pt)
+                   + pos.count_in_hand(WHITE, pt) + pos.count_in_hand(BLACK, pt);
+
+    let phase = Phase::PAWN * count(PAWN) +
+                Phase::KNIGHT * count(KNIGHT) + 
+                Phase::BISHOP * count(BISHOP) +
+                Phase::ROOK * count(ROOK) +
+                Phase::QUEEN * count(QUEEN);
 
-    let egs = pos.psq_score().eg();
-    let mgs = pos.psq_score().mg();
+    let mut score = pos.psq_score();
+    if pos.variant().has_drops() {
+        score += in_hand_score(pos, WHITE) - in_hand_score(pos, BLACK);
+    }
+
+    match pos.variant() {
+        Variant::ThreeCheck => {
+            score += CHECKS_GIVEN_BONUS[pos.checks_given(WHITE) as usize]
+                   - CHECKS_GIVEN_BONUS[pos.checks_given(BLACK) as usize];
+        }
+        Variant::KingOfTheHill => score += hill_score(pos, WHITE) - hill_score(pos, BLACK),
+        _ => {}
+    }
+
+    let mut egs = score.eg();
+    if pos.variant().is_chess() {
+        let strong = if egs > Value::ZERO { WHITE } else { BLACK };
+        egs = Value(egs.0 * endgame::scale_factor(pos, strong) / endgame::SCALE_FACTOR_NORMAL);
+    }
+    let mgs = score.mg();
 
     let value = (egs * Phase::MIDGAME + (mgs - egs) * phase) / Phase::MIDGAME;
 
@@ -32,23 +88,83 @@ pub fn evaluate(pos: &Position) -> Value {
     }
 }
 
+// hill_score() returns the bonus for the king of the given color getting
+// closer to the center, which it wins the game by reaching.
+fn hill_score(pos: &Position, c: Color) -> Score {
+    let ksq = pos.square(c, KING);
+    let distance = CENTER.into_iter().map(|s| square_distance(ksq, s)).min().unwrap();
+    HILL_BONUS[distance as usize]
+}
+
+// in_hand_score() returns the material and bonus of the pieces in the hand
+// of the given color.
+fn in_hand_score(pos: &Position, c: Color) -> Score {
+    let mut score = Score::ZERO;
+    for pt in [PAWN, KNIGHT, BISHOP, ROOK, QUEEN] {
+        let pc = Piece::make(c, pt);
+        let value = Score::make(piece_value(MG, pc), piece_value(EG, pc)) + IN_HAND_BONUS[pt.0 as usize];
+        score += value * pos.count_in_hand(c, pt);
+    }
+    score
+}
+
 impl Position {
-    // is_draw() tests whether the position is drawn by 50-move rule or by
-    // repetition. It does not detect stalemates.
+    // is_draw() tests whether the position is drawn by 50-move rule, by
+    // repetition or by insufficient material. It does not detect stalemates.
+    // A position repeated once within the search tree is a draw, while one
+    // first repeated before the root needs a third occurrence (negative
+    // repetition).
 
     pub fn is_draw(&self, ply: i32) -> bool {
         if self.rule50_count() > 99 {
 
-            let mut list = [ExtMove {m: Move::NONE, value: Value::ZERO}; MAX_MOVES];
-            let num_moves = generate_legal(&self, &mut list, 0);
-
-            if self.checkers() == EMPTY_BB || num_moves != 0 {
+            // Legal moves are only needed to tell a checkmate apart
+            if self.checkers() == EMPTY_BB {
                 return true;
+            }
 
+            if !self.legal_moves().is_empty() {
+                return true;
             }
         }
 
+        if self.is_insufficient_material() {
+            return true;
+        }
+
         self.st().repetition != 0 && self.st().repetition < ply 
 
     }
+
+    // is_insufficient_material() tests whether neither side can possibly
+    // checkmate: KvK, KBvK, KNvK, and kings with bishops all on squares of
+    // the same colour.
+
+    pub fn is_insufficient_material(&self) -> bool {
+        // In atomic chess a single knight, bishop or rook cannot win against
+        // a bare king. In the other variants pieces can be dropped back, be
+        // lost on purpose, give checks or walk the king to the center.
+        if !self.variant().is_chess() {
+            if !self.variant().has_explosions() {
+                return false;
+            }
+            let others = self.pieces() & !self.pieces_p(KING);
+            return popcount(self.pieces_p(KING)) == 2
+                && !more_than_one(others)
+                && others & self.pieces_pp(PAWN, QUEEN) == EMPTY_BB;
+        }
+
+        if self.pieces_pp(PAWN, ROOK) | self.pieces_p(QUEEN) != EMPTY_BB {
+            return false;
+        }
+
+        let minors = self.pieces_pp(KNIGHT, BISHOP);
+        if popcount(minors) <= 1 {
+            return true;
+        }
+
+        let bishops = self.pieces_p(BISHOP);
+        self.pieces_p(KNIGHT) == EMPTY_BB
+            && (bishops & DARK_SQUARES == EMPTY_BB || bishops & !DARK_SQUARES == EMPTY_BB)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,367 @@
+use crate::types::piece::{Color, WHITE};
+use crate::types::r#move::Move;
+use crate::types::score::Value;
+use crate::position::Position;
+use crate::movegen::DropMoveList;
+use crate::pgn::PgnGame;
+use crate::uci::START_FEN;
+use crate::variant::Variant;
+
+use std::fmt;
+
+/// The state of a game. Checkmate, stalemate, fivefold repetition, the
+/// 75-move rule and insufficient material end the game automatically, while
+/// threefold repetition and the 50-move rule are draws that may be claimed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Ongoing,
+    Checkmate(Color),
+    VariantWin(Color),
+    Stalemate,
+    ThreefoldRepetition,
+    FivefoldRepetition,
+    FiftyMoveRule,
+    SeventyFiveMoveRule,
+    InsufficientMaterial,
+}
+
+impl GameResult {
+    /// is_over() tests whether the game has ended without any claim
+    pub fn is_over(self) -> bool {
+        !matches!(self, GameResult::Ongoing | GameResult::ThreefoldRepetition | GameResult::FiftyMoveRule)
+    }
+
+    pub fn is_draw(self) -> bool {
+        !matches!(self, GameResult::Ongoing | GameResult::Checkmate(_) | GameResult::VariantWin(_))
+    }
+
+    /// winner() returns the color of the winning side, if any
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            GameResult::Checkmate(c) | GameResult::VariantWin(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// pgn_result() returns the result string used by PGN. Claimable draws
+    /// are reported as draws.
+    pub fn pgn_result(self) -> &'static str {
+        match self {
+            GameResult::Ongoing => "*",
+            GameResult::Checkmate(c) | GameResult::VariantWin(c) if c == WHITE => "1-0",
+            GameResult::Checkmate(_) | GameResult::VariantWin(_) => "0-1",
+            _ => "1/2-1/2",
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            GameResult::Ongoing => "ongoing",
+            GameResult::Checkmate(c) if *c == WHITE => "white wins by checkmate",
+            GameResult::Checkmate(_) => "black wins by checkmate",
+            GameResult::VariantWin(c) if *c == WHITE => "white wins by variant rules",
+            GameResult::VariantWin(_) => "black wins by variant rules",
+            GameResult::Stalemate => "draw by stalemate",
+            GameResult::ThreefoldRepetition => "draw by threefold repetition",
+            GameResult::FivefoldRepetition => "draw by fivefold repetition",
+            GameResult::FiftyMoveRule => "draw by 50-move rule",
+            GameResult::SeventyFiveMoveRule => "draw by 75-move rule",
+            GameResult::InsufficientMaterial => "draw by insufficient material",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A game wraps a Position together with its starting position and the moves
+/// played, and only accepts legal moves.
+#[derive(Debug, Clone)]
+pub struct Game {
+    pos: Position,
+    start_fen: String,
+    moves: Vec<Move>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    /// new() creates a game from the standard starting position
+    pub fn new() -> Game {
+        Game::from_fen(START_FEN, false)
+    }
+
+    pub fn from_fen(fen: &str, chess960: bool) -> Game {
+        Game::from_variant_fen(Variant::Chess, fen, chess960)
+    }
+
+    pub fn from_variant_fen(variant: Variant, fen: &str, chess960: bool) -> Game {
+        let mut pos = Position::new();
+        pos.set_variant(variant, fen, chess960);
+        Game { start_fen: pos.fen(), pos, moves: Vec::new() }
+    }
+
+    /// from_pgn() replays the main line of a PGN game
+    pub fn from_pgn(pgn: &PgnGame) -> Result<Game, String> {
+        let mut game = Game::from_variant_fen(pgn.variant(), pgn.start_fen(), pgn.is_chess960());
+        for pgn_move in &pgn.moves {
+            game.play(pgn_move.m)?;
+        }
+        Ok(game)
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.pos
+    }
+
+    pub fn start_fen(&self) -> &str {
+        &self.start_fen
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.pos.side_to_move()
+    }
+
+    pub fn legal_moves(&self) -> DropMoveList {
+        self.pos.legal_moves()
+    }
+
+    pub fn is_legal(&self, m: Move) -> bool {
+        m != Move::NONE && self.legal_moves().contains(m)
+    }
+
+    /// play() makes a move, which must be legal in the current position
+    pub fn play(&mut self, m: Move) -> Result<(), String> {
+        if !self.is_legal(m) {
+            return Err(format!("illegal move {} in {}", m.to_string(self.pos.is_chess960()), self.pos.fen()));
+        }
+        self.pos.do_move(m);
+        self.moves.push(m);
+        Ok(())
+    }
+
+    /// play_uci() makes a move given in coordinate notation
+    pub fn play_uci(&mut self, s: &str) -> Result<Move, String> {
+        let m = Move::from_uci(&self.pos, s.trim())?;
+        self.play(m)?;
+        Ok(m)
+    }
+
+    /// play_san() makes a move given in standard algebraic notation
+    pub fn play_san(&mut self, s: &str) -> Result<Move, String> {
+        let m = self.pos.parse_san(s);
+        if m == Move::NONE {
+            return Err(format!("illegal or ambiguous move {} in {}", s, self.pos.fen()));
+        }
+        self.play(m)?;
+        Ok(m)
+    }
+
+    /// undo() takes back the last move, if any
+    pub fn undo(&mut self) -> Option<Move> {
+        let m = self.moves.pop()?;
+        self.pos.undo_move(m);
+        Some(m)
+    }
+
+    /// repetitions() returns how many times the current position occurred
+    /// in the game, counting the current one.
+    pub fn repetitions(&self) -> usize {
+        let st = self.pos.st();
+        let states = &self.pos.states;
+        let last = states.len() - 1;
+        let end = std::cmp::min(st.rule50, st.plies_from_null) as usize;
+
+        1 + (4..=end).step_by(2).filter(|&i| states[last - i].key == st.key).count()
+    }
+
+    /// result() returns the state of the game in the current position
+    pub fn result(&self) -> GameResult {
+        if let Some(v) = self.pos.variant_end(0) {
+            let us = self.side_to_move();
+            return GameResult::VariantWin(if v > Value::DRAW { us } else { !us });
+        }
+
+        if self.legal_moves().is_empty() {
+            return if self.pos.variant().stalemate_wins() {
+                GameResult::VariantWin(self.side_to_move())
+            } else if self.pos.checkers() != 0 {
+                GameResult::Checkmate(!self.side_to_move())
+            } else {
+                GameResult::Stalemate
+            };
+        }
+
+        if self.pos.is_insufficient_material() {
+            return GameResult::InsufficientMaterial;
+        }
+
+        let repetitions = self.repetitions();
+        let rule50 = self.pos.rule50_count();
+
+        if repetitions >= 5 {
+            GameResult::FivefoldRepetition
+        } else if rule50 >= 150 {
+            GameResult::SeventyFiveMoveRule
+        } else if repetitions >= 3 {
+            GameResult::ThreefoldRepetition
+        } else if rule50 >= 100 {
+            GameResult::FiftyMoveRule
+        } else {
+            GameResult::Ongoing
+        }
+    }
+
+    pub fn fen(&self) -> String {
+        self.pos.fen()
+    }
+
+    /// to_pgn_game() converts the game to a PgnGame, with the SetUp and FEN
+    /// tags when the game does not start from the standard position.
+    pub fn to_pgn_game(&self) -> PgnGame {
+        let mut pgn = PgnGame::new();
+        for (name, value) in [("Event", "?"), ("Site", "?"), ("Date", "????.??.??"),
+                              ("Round", "?"), ("White", "?"), ("Black", "?")] {
+            pgn.set_tag(name, value);
+        }
+        if !self.pos.variant().is_chess() {
+            pgn.set_tag("Variant", self.pos.variant().pgn_name());
+        } else if self.pos.is_chess960() {
+            pgn.set_tag("Variant", "Chess960");
+        }
+        if self.start_fen != START_FEN || self.pos.is_chess960() {
+            pgn.set_tag("SetUp", "1");
+            pgn.set_tag("FEN", &self.start_fen);
+        }
+
+        let mut pos = Position::new();
+        pos.set_variant(self.pos.variant(), &self.start_fen, self.pos.is_chess960());
+        for &m in &self.moves {
+            pgn.push(&mut pos, m);
+            pos.do_move(m);
+        }
+        pgn.result = self.result().pgn_result().to_string();
+        pgn
+    }
+
+    pub fn to_pgn(&self) -> String {
+        self.to_pgn_game().to_pgn()
+    }
+}
+
+#[cfg(test)]
+mod game_test {
+
+    use super::*;
+    use crate::types::piece::BLACK;
+
+    fn play_all(game: &mut Game, moves: &str) {
+        for m in moves.split_whitespace() {
+            game.play_san(m).unwrap();
+        }
+    }
+
+    #[test]
+    fn checkmate_and_pgn_export() {
+        let mut game = Game::new();
+        play_all(&mut game, "f3 e5 g4 Qh4#");
+
+        assert_eq!(game.result(), GameResult::Checkmate(BLACK));
+        assert!(game.result().is_over());
+        assert!(game.to_pgn().contains("1. f3 e5 2. g4 Qh4# 0-1"));
+
+        let pgn = crate::pgn::parse_pgn(&game.to_pgn()).unwrap();
+        let replayed = Game::from_pgn(&pgn[0]).unwrap();
+        assert_eq!(replayed.fen(), game.fen());
+
+        let mate = game.moves()[3];
+        assert_eq!(game.undo(), Some(mate));
+        assert_eq!(game.result(), GameResult::Ongoing);
+    }
+
+    #[test]
+    fn illegal_moves_and_undo() {
+        let mut game = Game::new();
+        assert!(game.play_uci("e2e5").is_err());
+        assert!(game.play_san("Nf6").is_err());
+
+        game.play_uci("e2e4").unwrap();
+        game.play_san("c5").unwrap();
+        assert_eq!(game.moves().len(), 2);
+
+        game.undo();
+        game.undo();
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.fen(), START_FEN);
+    }
+
+    #[test]
+    fn atomic_king_explosion() {
+        let mut game = Game::from_variant_fen(Variant::Atomic, START_FEN, false);
+        play_all(&mut game, "e4 e5 Qh5 Nc6 Qxf7");
+
+        assert_eq!(game.result(), GameResult::VariantWin(WHITE));
+        assert_eq!(game.result().pgn_result(), "1-0");
+        assert!(game.legal_moves().is_empty());
+
+        // The variant is kept by the PGN Variant tag
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[Variant \"Atomic\"]"));
+        let replayed = Game::from_pgn(&crate::pgn::parse_pgn(&pgn).unwrap()[0]).unwrap();
+        assert_eq!(replayed.fen(), game.fen());
+        assert_eq!(replayed.result(), GameResult::VariantWin(WHITE));
+
+        let game = Game::from_variant_fen(Variant::Atomic, "8/8/3k4/8/8/3K4/8/6R1 w - - 0 1", false);
+        assert_eq!(game.result(), GameResult::InsufficientMaterial);
+    }
+
+    #[test]
+    fn antichess_results() {
+        // Losing the last piece wins
+        let mut game = Game::from_variant_fen(Variant::Antichess, "8/8/8/8/8/8/1k6/K7 w - - 0 1", false);
+        game.play_san("Kxb2").unwrap();
+        assert_eq!(game.result(), GameResult::VariantWin(BLACK));
+
+        // So does being stalemated
+        let game = Game::from_variant_fen(Variant::Antichess, "8/8/8/8/p7/P7/8/8 w - - 0 1", false);
+        assert_eq!(game.result(), GameResult::VariantWin(WHITE));
+    }
+
+    #[test]
+    fn draws() {
+        let mut game = Game::new();
+        for _ in 0..2 {
+            play_all(&mut game, "Nf3 Nf6 Ng1 Ng8");
+        }
+        assert_eq!(game.repetitions(), 3);
+        assert_eq!(game.result(), GameResult::ThreefoldRepetition);
+        assert!(!game.result().is_over());
+
+        for _ in 0..2 {
+            play_all(&mut game, "Nf3 Nf6 Ng1 Ng8");
+        }
+        assert_eq!(game.result(), GameResult::FivefoldRepetition);
+        assert!(game.result().is_over());
+
+        let game = Game::from_fen("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", false);
+        assert_eq!(game.result(), GameResult::Stalemate);
+
+        let game = Game::from_fen("8/8/3k4/8/8/3K4/8/6N1 w - - 0 1", false);
+        assert_eq!(game.result(), GameResult::InsufficientMaterial);
+
+        let game = Game::from_fen("8/8/3k4/8/8/3K4/8/6R1 w - - 100 90", false);
+        assert_eq!(game.result(), GameResult::FiftyMoveRule);
+
+        let game = Game::from_fen("8/8/3k4/8/8/3K4/8/6R1 w - - 150 120", false);
+        assert_eq!(game.result(), GameResult::SeventyFiveMoveRule);
+        assert_eq!(game.result().pgn_result(), "1/2-1/2");
+    }
+}
@@ -14,3 +14,11 @@ pub mod tt;
 pub mod timeman;
 pub mod uciset;
 pub mod tune;
+pub mod pgn;
+pub mod testsuite;
+pub mod benchmark;
+pub mod book;
+pub mod tablebase;
+pub mod endgame;
+pub mod game;
+pub mod variant;
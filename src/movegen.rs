@@ -7,7 +7,7 @@ use crate::types::bitboard::*;
 use crate::types::square::*;
 use crate::types::piece::*;
 use crate::types::r#move::*;
-use crate::types::score::Value;
+use crate::types::score::{Value, MAX_MOVES, MAX_DROP_MOVES};
 use crate::attacks::attack_bb::*;
 
 
@@ -58,12 +58,28 @@ impl PartialEq for ExtMove {
 }
 
 
-fn generate_moves(us: Color, pt: PieceType, checks: bool,
+// The generators are specialised at compile time over the side to move (US),
+// the generation type (GT) and whether only legal moves are generated
+// (LEGAL_ONLY). Then the pinned pieces only move along the ray of the pin
+// and the king only steps to squares that are not attacked, so that no move
+// has to be filtered afterwards. LEGAL_ONLY is used with EVASIONS and
+// NON_EVASIONS.
+
+// pin_legal() tests whether a piece that may be pinned to the king keeps it
+// covered, that is it is not pinned or moves along the ray of the pin.
+#[inline(always)]
+fn pin_legal(pinned: Bitboard, ksq: Square, from: Square, to: Square) -> bool {
+    pinned & from == EMPTY_BB || aligned(from, to, ksq)
+}
+
+fn generate_moves<const US: u32, const GT: u32, const LEGAL_ONLY: bool>(pt: PieceType,
     pos: &Position, list: &mut [ExtMove], mut idx: usize, 
     target: Bitboard
 ) -> usize {
-    debug_assert!(pt != KING && pt != PAWN);
+    debug_assert!(pt != PAWN);
 
+    let us = Color(US);
+    let checks = GenType(GT) == QUIET_CHECKS;
     let bb = pos.pieces_cp(us, pt);
 
     for from in bb {
@@ -75,6 +91,11 @@ fn generate_moves(us: Color, pt: PieceType, checks: bool,
             b &= pos.check_squares(pt);
         }
 
+        // A pinned piece can only move along the line of the pin
+        if LEGAL_ONLY && pos.blockers_for_king(us) & from != EMPTY_BB {
+            b &= line_bb(pos.square(us, KING), from);
+        }
+
         for to in b {
             list[idx].m = Move::make(from, to);
             idx += 1;
@@ -84,9 +105,11 @@ fn generate_moves(us: Color, pt: PieceType, checks: bool,
     idx
 }
 
-fn generate_pawn_moves(us: Color, gen_type: GenType,
+fn generate_pawn_moves<const US: u32, const GT: u32, const LEGAL_ONLY: bool>(
     pos: &Position, list: &mut [ExtMove], mut idx: usize, target: Bitboard) -> usize {
 
+    let us = Color(US);
+    let gen_type = GenType(GT);
     let them = !us;
     //let trank_8bb = if us == WHITE { RANK_8_BB } else { RANK_1_BB };
     let trank_7bb = if us == WHITE { RANK_7_BB } else { RANK_2_BB };
@@ -101,6 +124,10 @@ fn generate_pawn_moves(us: Color, gen_type: GenType,
     let pawns_on_7     = pos.pieces_cp(us, PAWN) &  trank_7bb;
     let pawns_not_on_7 = pos.pieces_cp(us, PAWN) & !trank_7bb;
 
+    // The pinned pawns, only used when generating legal moves
+    let pinned = if LEGAL_ONLY { pos.blockers_for_king(us) & pos.pieces_cp(us, PAWN) } else { EMPTY_BB };
+    let ksq = if LEGAL_ONLY { pos.square(us, KING) } else { Square::NONE };
+
     // Single and double pawn pushes, no promotions
     if gen_type != CAPTURES {
 
@@ -123,13 +150,17 @@ fn generate_pawn_moves(us: Color, gen_type: GenType,
         }
 
         for to in b1 {
-            list[idx].m = Move::make(to - up, to);
-            idx += 1;
+            if !LEGAL_ONLY || pin_legal(pinned, ksq, to - up, to) {
+                list[idx].m = Move::make(to - up, to);
+                idx += 1;
+            }
         }
 
         for to in b2 {
-            list[idx].m = Move::make(to - up - up, to);
-            idx += 1;
+            if !LEGAL_ONLY || pin_legal(pinned, ksq, to - up - up, to) {
+                list[idx].m = Move::make(to - up - up, to);
+                idx += 1;
+            }
         }
 
     }
@@ -146,15 +177,21 @@ fn generate_pawn_moves(us: Color, gen_type: GenType,
         }
 
         for to in b1 {
-            idx = make_promotions(gen_type, up_right, list, idx, to);
+            if !LEGAL_ONLY || pin_legal(pinned, ksq, to - up_right, to) {
+                idx = make_promotions(gen_type, up_right, list, idx, to);
+            }
         }
 
         for to in b2 {
-            idx = make_promotions(gen_type, up_left, list, idx, to);
+            if !LEGAL_ONLY || pin_legal(pinned, ksq, to - up_left, to) {
+                idx = make_promotions(gen_type, up_left, list, idx, to);
+            }
         }
 
         for to in b3 {
-            idx = make_promotions(gen_type, up, list, idx, to);
+            if !LEGAL_ONLY || pin_legal(pinned, ksq, to - up, to) {
+                idx = make_promotions(gen_type, up, list, idx, to);
+            }
         }
 
     }
@@ -167,13 +204,17 @@ fn generate_pawn_moves(us: Color, gen_type: GenType,
         let b2 = pawns_not_on_7.shift(up_left) & enemies;
 
         for to in b1 {
-            list[idx].m = Move::make(to - up_right, to);
-            idx += 1;
+            if !LEGAL_ONLY || pin_legal(pinned, ksq, to - up_right, to) {
+                list[idx].m = Move::make(to - up_right, to);
+                idx += 1;
+            }
         }
 
         for to in b2 {
-            list[idx].m = Move::make(to - up_left, to);
-            idx += 1;
+            if !LEGAL_ONLY || pin_legal(pinned, ksq, to - up_left, to) {
+                list[idx].m = Move::make(to - up_left, to);
+                idx += 1;
+            }
         }
 
         if pos.ep_square() != Square::NONE {
@@ -188,9 +229,14 @@ fn generate_pawn_moves(us: Color, gen_type: GenType,
 
             debug_assert!(b1 != EMPTY_BB);
 
+            // En passant captures are rare enough to be tested by legal(),
+            // which also catches the pins along the rank of the two pawns.
             for from in b1 {
-                list[idx].m = Move::make_special(EN_PASSANT, from, pos.ep_square());
-                idx += 1;
+                let m = Move::make_special(EN_PASSANT, from, pos.ep_square());
+                if !LEGAL_ONLY || pos.legal(m) {
+                    list[idx].m = m;
+                    idx += 1;
+                }
             }
         }
     }
@@ -218,30 +264,77 @@ fn make_promotions(gen_type: GenType, d: Direction,
     idx
 }
 
-fn generate_all(us: Color, gen_type: GenType, 
+// generate_drops() generates the drops of the pieces in hand on the empty
+// target squares. Pawns cannot be dropped on the first or last rank.
+fn generate_drops(us: Color, gen_type: GenType,
+    pos: &Position, list: &mut [ExtMove], mut idx: usize, target: Bitboard) -> usize {
+
+    for pt in [PAWN, KNIGHT, BISHOP, ROOK, QUEEN] {
+        if pos.count_in_hand(us, pt) == 0 {
+            continue;
+        }
+
+        let mut b = target & !pos.pieces();
+
+        if pt == PAWN {
+            b &= !(RANK_1_BB | RANK_8_BB);
+        }
+
+        if gen_type == QUIET_CHECKS {
+            b &= pos.check_squares(pt);
+        }
+
+        for to in b {
+            list[idx].m = Move::make_drop(pt, to);
+            idx += 1;
+        }
+    }
+
+    idx
+}
+
+fn generate_all<const US: u32, const GT: u32, const LEGAL_ONLY: bool>(
     pos: &Position, list: &mut [ExtMove], mut idx: usize) -> usize {
 
+    let us = Color(US);
+    let gen_type = GenType(GT);
     debug_assert!(gen_type != LEGAL );
+    debug_assert!(!LEGAL_ONLY || gen_type == EVASIONS || gen_type == NON_EVASIONS);
     let checks = gen_type == QUIET_CHECKS;
 
-    let ksq = pos.square(us, KING);
     let target = match gen_type {
-            EVASIONS =>      between_bb(ksq, lsb(pos.checkers())),
+            EVASIONS =>      between_bb(pos.square(us, KING), lsb(pos.checkers())),
             NON_EVASIONS => !pos.pieces_c( us),
             CAPTURES =>      pos.pieces_c(!us), 
             _ =>            !pos.pieces(     )
 
     } ;
 
+    // Kings are ordinary pieces in antichess, and there may be none or
+    // several of them.
+    if pos.variant().king_is_piece() {
+        idx = generate_pawn_moves::<US, GT, false>(pos, list, idx, target);
+        for pt in [KNIGHT, BISHOP, ROOK, QUEEN, KING] {
+            idx = generate_moves::<US, GT, false>(pt, pos, list, idx, target);
+        }
+        return idx;
+    }
+
+    let ksq = pos.square(us, KING);
+
     // Skip generating non-king moves when in double check
     if gen_type != EVASIONS || !more_than_one(pos.checkers())
     {
         //println!("Here");
-        idx = generate_pawn_moves(us, gen_type, pos, list, idx, target);
-        idx = generate_moves(us, KNIGHT, checks, pos, list, idx, target);
-        idx = generate_moves(us, BISHOP, checks, pos, list, idx, target);
-        idx = generate_moves(us, ROOK,   checks, pos, list, idx, target);
-        idx = generate_moves(us, QUEEN,  checks, pos, list, idx, target); 
+        idx = generate_pawn_moves::<US, GT, LEGAL_ONLY>(pos, list, idx, target);
+        idx = generate_moves::<US, GT, LEGAL_ONLY>(KNIGHT, pos, list, idx, target);
+        idx = generate_moves::<US, GT, LEGAL_ONLY>(BISHOP, pos, list, idx, target);
+        idx = generate_moves::<US, GT, LEGAL_ONLY>(ROOK,   pos, list, idx, target);
+        idx = generate_moves::<US, GT, LEGAL_ONLY>(QUEEN,  pos, list, idx, target); 
+
+        if gen_type != CAPTURES && pos.variant().has_drops() {
+            idx = generate_drops(us, gen_type, pos, list, idx, target);
+        }
     }
 
     if !checks || (pos.blockers_for_king(!us) & ksq) != EMPTY_BB
@@ -250,11 +343,21 @@ fn generate_all(us: Color, gen_type: GenType,
         let mut b = pseudo_attacks(KING, ksq);
         b &= if gen_type == EVASIONS { !pos.pieces_c(us) } else { target };
 
+        // Kings cannot capture in atomic chess
+        if pos.variant().has_explosions() {
+            b &= !pos.pieces();
+        }
+
         if checks { 
             b &= !pseudo_attacks(QUEEN, pos.square(!us, KING));
         }
 
         for to in b {
+            // The king cannot step to an attacked square, nor away from a
+            // slider along the line of its check.
+            if LEGAL_ONLY && pos.attackers_to_occ(to, pos.pieces() ^ ksq) & pos.pieces_c(!us) != EMPTY_BB {
+                continue;
+            }
             list[idx].m = Move::make(ksq, to);
             idx += 1;
 
@@ -263,8 +366,11 @@ fn generate_all(us: Color, gen_type: GenType,
         if gen_type == QUIETS || gen_type == NON_EVASIONS && pos.has_castling_right(castling_right_c(us, ANY_CASTLING)) {
             for cr in [castling_right_c(us, KING_SIDE), castling_right_c(us, QUEEN_SIDE)] {
                 if !pos.castling_impeded(cr) && pos.has_castling_right(cr) {
-                    list[idx].m = Move::make_special(CASTLING, ksq, pos.castling_rook_square(cr));
-                    idx += 1;
+                    let m = Move::make_special(CASTLING, ksq, pos.castling_rook_square(cr));
+                    if !LEGAL_ONLY || pos.legal(m) {
+                        list[idx].m = m;
+                        idx += 1;
+                    }
                 }
             }
         }
@@ -274,48 +380,205 @@ fn generate_all(us: Color, gen_type: GenType,
 
 }
 
+// generate_for() calls the generator specialised for the side to move and
+// the generation type.
+fn generate_for<const LEGAL_ONLY: bool>(gen_type: GenType,
+    pos: &Position, list: &mut [ExtMove], idx: usize) -> usize {
+
+    match (pos.side_to_move(), gen_type) {
+        (WHITE, CAPTURES)     => generate_all::<{ WHITE.0 }, { CAPTURES.0 },     LEGAL_ONLY>(pos, list, idx),
+        (WHITE, QUIETS)       => generate_all::<{ WHITE.0 }, { QUIETS.0 },       LEGAL_ONLY>(pos, list, idx),
+        (WHITE, QUIET_CHECKS) => generate_all::<{ WHITE.0 }, { QUIET_CHECKS.0 }, LEGAL_ONLY>(pos, list, idx),
+        (WHITE, EVASIONS)     => generate_all::<{ WHITE.0 }, { EVASIONS.0 },     LEGAL_ONLY>(pos, list, idx),
+        (WHITE, _)            => generate_all::<{ WHITE.0 }, { NON_EVASIONS.0 }, LEGAL_ONLY>(pos, list, idx),
+        (_, CAPTURES)         => generate_all::<{ BLACK.0 }, { CAPTURES.0 },     LEGAL_ONLY>(pos, list, idx),
+        (_, QUIETS)           => generate_all::<{ BLACK.0 }, { QUIETS.0 },       LEGAL_ONLY>(pos, list, idx),
+        (_, QUIET_CHECKS)     => generate_all::<{ BLACK.0 }, { QUIET_CHECKS.0 }, LEGAL_ONLY>(pos, list, idx),
+        (_, EVASIONS)         => generate_all::<{ BLACK.0 }, { EVASIONS.0 },     LEGAL_ONLY>(pos, list, idx),
+        (_, _)                => generate_all::<{ BLACK.0 }, { NON_EVASIONS.0 }, LEGAL_ONLY>(pos, list, idx),
+    }
+}
+
 pub fn generate(gen_type: GenType, 
     pos: &Position, list: &mut [ExtMove], idx: usize) -> usize {
 
     debug_assert!(gen_type != LEGAL );
-    debug_assert!((gen_type == EVASIONS) == (pos.checkers() != EMPTY_BB));
 
+    // The rules of the variants plug in here, standard chess skips them all
+    // with a single test.
+    if !pos.variant().is_chess() {
+        // There are no moves once the game is over by the rules of the variant
+        if pos.variant().has_variant_end() && pos.variant_end(0).is_some() {
+            return idx;
+        }
+
+        if pos.variant().compulsory_captures() {
+            return generate_antichess(gen_type, pos, list, idx);
+        }
+
+        // In atomic chess a check can also be met by blowing up the enemy
+        // king, so all moves are generated and legal() sorts them out.
+        if pos.variant().has_explosions() && gen_type == EVASIONS {
+            return generate_for::<false>(NON_EVASIONS, pos, list, idx);
+        }
+    }
+
+    debug_assert!((gen_type == EVASIONS) == (pos.checkers() != EMPTY_BB)
+        || pos.variant().has_explosions());
 
-    generate_all(pos.side_to_move(), gen_type, pos, list, idx)
+    generate_for::<false>(gen_type, pos, list, idx)
 
 }
 
-/// generate_legal() generates all the legal moves in the given position
+/// generate_antichess() generates the moves of an antichess position, where
+/// captures are compulsory. CAPTURES generates all the captures, QUIETS the
+/// other moves only when there is no capture, and NON_EVASIONS the legal
+/// moves. There are no checks in antichess.
+fn generate_antichess(gen_type: GenType,
+    pos: &Position, list: &mut [ExtMove], idx: usize) -> usize {
+
+    let end = generate_for::<false>(NON_EVASIONS, pos, list, idx);
+
+    // Each promotion can also be to a king
+    let mut end_promotions = end;
+    for i in idx..end {
+        let m = list[i].m;
+        if m.move_type() == PROMOTION && m.promotion_type() == QUEEN {
+            list[end_promotions].m = Move::make_prom(m.from(), m.to(), KING);
+            end_promotions += 1;
+        }
+    }
+
+    // Move the captures to the front of the list
+    let mut end_captures = idx;
+    for i in idx..end_promotions {
+        if pos.capture(list[i].m) {
+            list.swap(end_captures, i);
+            end_captures += 1;
+        }
+    }
+
+    let has_captures = end_captures > idx;
+    match gen_type {
+        CAPTURES => end_captures,
+        QUIETS if !has_captures => end_promotions,
+        NON_EVASIONS if has_captures => end_captures,
+        NON_EVASIONS => end_promotions,
+        _ => idx,
+    }
+}
+
+/// generate_legal() generates all the legal moves in the given position,
+/// directly from the pins and the checks of the position.
 pub fn generate_legal(
     pos: &Position, list: &mut [ExtMove], idx: usize
 ) -> usize {
-    let us = pos.side_to_move();
-    let pinned = pos.blockers_for_king(us) & pos.pieces_c(us);
-    let ksq = pos.square(us, KING);
 
-    let pseudo = if pos.checkers() != 0 {
-        generate(EVASIONS, pos, list, idx)
-    } else {
-        generate(NON_EVASIONS, pos, list, idx)
-    };
-
-    let mut legal = idx;
-    for i in idx..pseudo {
-        let m = list[i].m;
-        //print!("{} {}\n", pos.fen(), m.to_string(false));
-        if (pinned == EMPTY_BB && m.from() != ksq && m.move_type() != EN_PASSANT)
-            || pos.legal(m) {   
-            list[legal].m = m;
-            legal += 1;
+    // Variants with their own legality test every move
+    if pos.variant().has_own_legality() {
+        let pseudo = generate(NON_EVASIONS, pos, list, idx);
+        let mut legal = idx;
+        for i in idx..pseudo {
+            if pos.legal(list[i].m) {
+                list[legal].m = list[i].m;
+                legal += 1;
+            }
         }
-    
+        return legal;
+    }
 
+    // There are no moves once the game is over by the rules of the variant
+    if pos.variant().has_variant_end() && pos.variant_end(0).is_some() {
+        return idx;
     }
 
-    for i in legal..pseudo+1{
-        list[i].m = Move::NONE;
+    let gen_type = if pos.checkers() != 0 { EVASIONS } else { NON_EVASIONS };
+    generate_for::<true>(gen_type, pos, list, idx)
+}
+
+/// MoveList holds the moves generated in a position, to be used instead of
+/// a bare ExtMove array when the move ordering values are not needed. The
+/// capacity N is MAX_MOVES for chess, and MAX_DROP_MOVES only for variants
+/// with drops (see DropMoveList).
+#[derive(Debug, Clone, Copy)]
+pub struct MoveList<const N: usize = MAX_MOVES> {
+    list: [ExtMove; N],
+    len: usize,
+}
+
+/// DropMoveList holds the moves of a position of any variant
+pub type DropMoveList = MoveList<MAX_DROP_MOVES>;
+
+impl<const N: usize> MoveList<N> {
+    /// legal() generates all the legal moves in the given position
+    #[inline]
+    pub fn legal(pos: &Position) -> MoveList<N> {
+        let mut ml = MoveList { list: [ExtMove::new(); N], len: 0 };
+        ml.len = generate_legal(pos, &mut ml.list, 0);
+        ml
+    }
+
+    /// pseudo_legal() generates all the pseudo-legal moves in the given
+    /// position, that is the evasions when in check.
+    pub fn pseudo_legal(pos: &Position) -> MoveList<N> {
+        let gen_type = if pos.checkers() != 0 { EVASIONS } else { NON_EVASIONS };
+        MoveList::generate(gen_type, pos)
+    }
+
+    pub fn generate(gen_type: GenType, pos: &Position) -> MoveList<N> {
+        let mut ml = MoveList { list: [ExtMove::new(); N], len: 0 };
+        ml.len = generate(gen_type, pos, &mut ml.list, 0);
+        ml
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, m: Move) -> bool {
+        self.iter().any(|x| x == m)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Move> + '_ {
+        self.list[..self.len].iter().map(|ext_move| ext_move.m)
     }
 
-    legal
+    pub fn as_slice(&self) -> &[ExtMove] {
+        &self.list[..self.len]
+    }
+}
+
+impl<const N: usize> std::ops::Index<usize> for MoveList<N> {
+    type Output = Move;
+
+    fn index(&self, idx: usize) -> &Move {
+        &self.as_slice()[idx].m
+    }
+}
+
+impl<'a, const N: usize> IntoIterator for &'a MoveList<N> {
+    type Item = Move;
+    type IntoIter = std::iter::Map<std::slice::Iter<'a, ExtMove>, fn(&ExtMove) -> Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list[..self.len].iter().map(|ext_move| ext_move.m)
+    }
+}
+
+// legal_moves() and pseudo_legal_moves() hold the moves of any variant.
+// The search and perft use a MoveList of the size of the variant instead.
+impl Position {
+    #[inline]
+    pub fn legal_moves(&self) -> DropMoveList {
+        MoveList::legal(self)
+    }
+
+    pub fn pseudo_legal_moves(&self) -> DropMoveList {
+        MoveList::pseudo_legal(self)
+    }
 }
 
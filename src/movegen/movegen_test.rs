@@ -1,8 +1,7 @@
 use crate::position::Position;
 use crate::types::r#move::{Move, EN_PASSANT, CASTLING};
 use crate::types::piece::*;
-use crate::types::score::{Value, MAX_MOVES};
-use super::{ExtMove, generate_legal};
+use crate::types::square::Square;
 
 
 
@@ -129,30 +128,24 @@ fn is_capture(pos: &Position, m: Move) -> bool {
 
 fn perft_stats(pos: &mut Position, depth: u32, stats: &mut Stats) {
 
-    let mut list = [ExtMove {m: Move::NONE, value: Value::ZERO}; MAX_MOVES];
-
     if depth == 0 {
         return ();
     }
 
-    let _num_moves = generate_legal(&pos, &mut list, 0);
-   
-    for ext_move in list{
-    
-        if ext_move.m == Move::NONE { break; }
+    for m in &pos.legal_moves() {
 
         if depth == 1 {
             stats.nodes += 1;
-            if pos.gives_check(ext_move.m) { stats.checks += 1; }
-            if is_capture(pos, ext_move.m) { stats.captures += 1};
-            if ext_move.m.move_type() == CASTLING {  stats.castles += 1; } 
-            if ext_move.m.move_type() == EN_PASSANT  { stats.en_passant += 1; }
+            if pos.gives_check(m) { stats.checks += 1; }
+            if is_capture(pos, m) { stats.captures += 1};
+            if m.move_type() == CASTLING {  stats.castles += 1; } 
+            if m.move_type() == EN_PASSANT  { stats.en_passant += 1; }
         }
 
     
-        pos.do_move(ext_move.m);
+        pos.do_move(m);
         perft_stats(pos, depth - 1, stats);
-        pos.undo_move(ext_move.m);
+        pos.undo_move(m);
 
         
         
@@ -238,3 +231,26 @@ fn perft4_stats() {
 
     
 
+
+#[test]
+fn move_list() {
+    let mut pos = Position::new();
+    pos.set("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", false);
+
+    let legal = pos.legal_moves();
+    let pseudo_legal = pos.pseudo_legal_moves();
+    assert_eq!(legal.len(), 48);
+    assert!(legal.iter().all(|m| pseudo_legal.contains(m)));
+    assert_eq!((&legal).into_iter().count(), legal.len());
+    assert_eq!(legal[0], legal.as_slice()[0].m);
+
+    // The en passant capture exposes the king along the rank
+    pos.set("8/8/8/8/k2Pp2Q/8/8/3K4 b - d3 0 1", false);
+    let ep = Move::make_special(EN_PASSANT, Square::E4, Square::D3);
+    assert!(pos.pseudo_legal_moves().contains(ep));
+    assert!(!pos.legal_moves().contains(ep));
+
+    assert!(Move::from_uci(&pos, "e4d3").is_err());
+    assert_eq!(Move::from_uci(&pos, "e4e3"), Ok(Move::make(Square::E4, Square::E3)));
+    assert!(Move::from_uci(&pos, "zz").is_err());
+}
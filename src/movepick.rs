@@ -3,10 +3,10 @@ use types::square::{RANK_2, RANK_8, relative_rank, pawn_push};
 use types::piece::{PAWN, KNIGHT, KING, NO_PIECE};
 use types::bitboard::{lsb, more_than_one};
 use types::r#move::{Move, NORMAL};
-use types::score::{Depth, Value, MAX_MOVES, MG, piece_value};
+use types::score::{Depth, Value, MAX_MOVES, MAX_DROP_MOVES, MG, piece_value};
 use crate::attacks::attack_bb::{between_bb};
 use crate::position::Position;
-use crate::movegen::{ExtMove, EVASIONS, NON_EVASIONS, CAPTURES, QUIETS, QUIET_CHECKS, generate};
+use crate::movegen::{ExtMove, MoveList, EVASIONS, CAPTURES, QUIETS, QUIET_CHECKS, generate};
 use crate::search;
 use std::ops;
 
@@ -77,19 +77,19 @@ fn pick_best(list: &mut [ExtMove]) -> ExtMove {
 // returns a new pseudo-legal move each time it is called, until there are
 // no moves left, when MOVE_NONE is returned. In order to improve the
 // efficiency of the alpha beta algorithm, MovePicker attempts to return the
-// moves which are most likely to get a cut off first.
+// moves which are most likely to get a cut off first. N is the size of the
+// move list, MAX_DROP_MOVES in variants with drops and MAX_MOVES otherwise.
 
-pub struct MovePicker {
+pub struct MovePicker<const N: usize> {
     cur: usize,
     end_moves: usize,
     end_bad_captures: usize,
     stage: Stage,
-    // depth: i32,
+    depth: Depth,
     // ply: usize,
     tt_move: Move,
     killers: [Move; 2],
-    history: search::History,
-    list: [ExtMove; MAX_MOVES as usize],
+    list: [ExtMove; N],
 }
 
 
@@ -99,8 +99,8 @@ pub struct MovePicker {
 /// captures, promotions and some checks) and how important good move ordering
 /// is at the current node.
 
-impl MovePicker {
-    pub fn new(pos: &Position, ttm: Move, ply: usize, depth: Depth, ss: &[search::Stack], history: search::History) -> MovePicker {
+impl<const N: usize> MovePicker<N> {
+    pub fn new(pos: &Position, ttm: Move, ply: usize, depth: Depth, ss: &[search::Stack]) -> MovePicker<N> {
         let mut stage = if pos.checkers() != 0 { Stage::EVASION_TT } else {
             if depth > Depth(0) {
                 Stage::MAIN_TT
@@ -109,7 +109,11 @@ impl MovePicker {
             }
         };
 
-        let tt_move = if pos.pseudo_legal(ttm) {
+        // The quiescence search only takes a capture or a promotion from
+        // the TT, unless all evasions are generated
+        let tt_move = if pos.pseudo_legal(ttm)
+            && (stage != Stage::QSEARCH_TT || pos.capture_or_promotion(ttm))
+        {
             ttm
         } else {
             Move::NONE
@@ -124,15 +128,15 @@ impl MovePicker {
             end_moves: 0,
             end_bad_captures: 0,
             stage: stage,
+            depth,
             tt_move: tt_move,
             killers: [ss[ply].killers[0], ss[ply].killers[1]],
-            history: history,
             // ply: ply,
-            list: [ExtMove {m: Move::NONE, value: Value::ZERO}; MAX_MOVES as usize],
+            list: [ExtMove {m: Move::NONE, value: Value::ZERO}; N],
         }
     }
 
-    pub fn next_move(&mut self, pos: &Position, skip_quiets: bool) -> Move {
+    pub fn next_move(&mut self, pos: &Position, history: &search::History, skip_quiets: bool) -> Move {
         loop { match self.stage {
             Stage::MAIN_TT | Stage::EVASION_TT | Stage::QSEARCH_TT => {
                 self.stage += 1;
@@ -192,7 +196,7 @@ impl MovePicker {
             Stage::QUIET_INIT => {
                 self.cur = self.end_bad_captures;
                 self.end_moves = generate(QUIETS, pos, &mut self.list, self.cur);
-                self.score_quiets(pos);
+                self.score_quiets(pos, history);
                 self.list[self.cur..self.end_moves].sort();
                 //partial_insertion_sort(&mut self.list[self.cur..self.end_moves], Value(-4000));
                 self.stage += 1;
@@ -241,9 +245,18 @@ impl MovePicker {
 
             Stage::QCAPTURES => {
                 while self.cur < self.end_moves {
-                    return self.pick_best().m; 
+                    let m = self.pick_best().m;
+                    if m != self.tt_move {
+                        return m;
+                    }
+                }
+
+                // Quiet checks are only generated at the first ply of the
+                // quiescence search
+                if self.depth < Depth::QS_CHECKS {
+                    break;
                 }
-                self.stage += 1;   
+                self.stage += 1;
             }
 
             Stage::QCHECKS_INIT => {
@@ -254,9 +267,12 @@ impl MovePicker {
 
             Stage::QCHECKS => {
                 while self.cur < self.end_moves {
-                    return self.pick_next(); 
+                    let m = self.pick_next();
+                    if m != self.tt_move {
+                        return m;
+                    }
                 }
-                break;  
+                break;
             }
 
             _ => { panic!("movepick") }
@@ -287,12 +303,14 @@ impl MovePicker {
 
     }
 
-    fn score_quiets(&mut self, pos: &Position) {
+    // score_quiets() orders the quiet moves by the history of the thread,
+    // which is borrowed rather than copied at each node.
+    fn score_quiets(&mut self, pos: &Position, history: &search::History) {
 
         for ext_move in self.list[self.cur..self.end_moves].iter_mut() {
-            let pc_from = pos.piece_on(ext_move.m.from());
+            let pc_from = pos.moved_piece(ext_move.m);
             let to = ext_move.m.to();
-            ext_move.value = self.history[pc_from][to];
+            ext_move.value = history[pc_from][to];
         }
 
     }
@@ -310,25 +328,15 @@ impl Position {
         let to = m.to();
         let pc = self.moved_piece(m);
 
-        // Use a slower but simpler function for uncommon cases
-        if m.move_type() != NORMAL {
-            let mut list = [ExtMove {m: Move::NONE, value: Value::ZERO}; MAX_MOVES as usize];
-            
+        // Use a slower but simpler function for uncommon cases, and for
+        // variants with their own legality, which is not checked below.
+        if m.move_type() != NORMAL || self.variant().has_own_legality() {
             // Skip legality check of generate_legal
-            let _num_moves = if self.checkers() != 0 {
-                generate(EVASIONS, self, &mut list, 0);
+            return if self.variant().has_drops() {
+                MoveList::<MAX_DROP_MOVES>::pseudo_legal(self).contains(m)
             } else {
-                generate(NON_EVASIONS, self, &mut list, 0);
+                MoveList::<MAX_MOVES>::pseudo_legal(self).contains(m)
             };
-
-            // let num_moves = generate_legal(self, &mut list, 0);
-
-            for pseudo_legal in &list {
-                if pseudo_legal.m == m { return true; }
-            }
-
-            return false;
-            
         }
 
         // It is not a promotion, so promotion piece must be empty
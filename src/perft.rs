@@ -1,12 +1,27 @@
 use crate::types::r#move::Move;
-use crate::types::score::{Depth, Value, MAX_MOVES};
+use crate::types::score::{Depth, MAX_MOVES, MAX_DROP_MOVES};
+use crate::movegen::MoveList;
 use crate::position::Position;
-use crate::movegen::{ExtMove, generate_legal};
+use crate::position::fen::is_chess960_fen;
+use crate::zobrist::Key;
+
+use std::fs;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
 
 
 pub fn perft<const ROOT: bool>(pos: &mut Position, depth: Depth) -> usize {
+    if pos.variant().has_drops() {
+        perft_n::<ROOT, MAX_DROP_MOVES>(pos, depth)
+    } else {
+        perft_n::<ROOT, MAX_MOVES>(pos, depth)
+    }
+}
 
-    let mut list = [ExtMove {m: Move::NONE, value: Value(0)}; MAX_MOVES];
+// perft_n() counts the leaf nodes with move lists of size N, the larger
+// size being only needed by the variants with drops.
+fn perft_n<const ROOT: bool, const N: usize>(pos: &mut Position, depth: Depth) -> usize {
 
     let leaf = depth == Depth(2);
     let mut cnt;// = 0;
@@ -15,29 +30,25 @@ pub fn perft<const ROOT: bool>(pos: &mut Position, depth: Depth) -> usize {
         println!("Starting perft{} on position {}", depth.0, pos.fen());
     }
 
-    let mut _num_moves = generate_legal(&pos, &mut list, 0);
-
-    for ext_move in list{
-        let m = ext_move.m;
-        if m == Move::NONE { break; }
+    for m in &MoveList::<N>::legal(pos) {
 
         if ROOT && depth.0 <= 1 {
             cnt = 1;
             nodes += 1;
         } else {
    
-            pos.do_move(ext_move.m);
+            pos.do_move(m);
 
             cnt = if leaf {
-                generate_legal(&pos, &mut list, 0)
+                MoveList::<N>::legal(pos).len()
             }
             else { 
-                perft::<false>(pos, depth - 1) 
+                perft_n::<false, N>(pos, depth - 1) 
             };
 
             nodes += cnt;
 
-            pos.undo_move(ext_move.m);
+            pos.undo_move(m);
         }
         if ROOT {
             println!("{}: {}", m.to_string(pos.is_chess960()), cnt);
@@ -47,11 +58,222 @@ pub fn perft<const ROOT: bool>(pos: &mut Position, depth: Depth) -> usize {
     nodes
 }
 
+// PerftTable is a hash table caching subtree node counts, shared by all the
+// perft threads. Each entry stores the key xored with the data, so that an
+// entry torn by concurrent writes is detected and ignored (lockless hashing).
+pub struct PerftTable {
+    entries: Vec<[AtomicU64; 2]>,
+}
+
+impl PerftTable {
+
+    pub fn new(size_mb: usize) -> PerftTable {
+        let count = ((size_mb << 20) / std::mem::size_of::<[AtomicU64; 2]>()).max(1);
+        PerftTable {
+            entries: (0..1usize << count.ilog2()).map(|_| [AtomicU64::new(0), AtomicU64::new(0)]).collect(),
+        }
+    }
+
+    fn entry(&self, key: Key) -> &[AtomicU64; 2] {
+        &self.entries[key as usize & (self.entries.len() - 1)]
+    }
+
+    // Data packs the node count in the upper 56 bits and the depth in the
+    // lower 8 bits.
+    pub fn probe(&self, key: Key, depth: Depth) -> Option<usize> {
+        let entry = self.entry(key);
+        let data = entry[1].load(Ordering::Relaxed);
+        if data != 0 && entry[0].load(Ordering::Relaxed) ^ data == key && (data & 0xFF) as i32 == depth.0 {
+            Some((data >> 8) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn save(&self, key: Key, depth: Depth, nodes: usize) {
+        let entry = self.entry(key);
+        let data = (nodes as u64) << 8 | depth.0 as u64;
+        entry[0].store(key ^ data, Ordering::Relaxed);
+        entry[1].store(data, Ordering::Relaxed);
+    }
+}
+
+// perft_hashed() counts the leaf nodes like perft(), caching the counts of
+// the subtrees in the perft table.
+fn perft_hashed<const N: usize>(pos: &mut Position, depth: Depth, table: &PerftTable) -> usize {
+
+    let moves = MoveList::<N>::legal(pos);
+
+    if depth <= Depth(1) {
+        return moves.len();
+    }
+
+    if let Some(nodes) = table.probe(pos.key(), depth) {
+        return nodes;
+    }
+
+    let mut nodes = 0;
+    for m in &moves {
+        pos.do_move(m);
+        nodes += perft_hashed::<N>(pos, depth - 1, table);
+        pos.undo_move(m);
+    }
+
+    table.save(pos.key(), depth, nodes);
+    nodes
+}
+
+// perft_split() splits the root moves among 'threads' threads sharing the
+// perft table, and returns the node count of each root move in move
+// generation order.
+pub fn perft_split(pos: &Position, depth: Depth, threads: usize, table: &PerftTable) -> Vec<(Move, usize)> {
+
+    let root_moves: Vec<Move> = pos.legal_moves().iter().collect();
+    let counts: Vec<AtomicUsize> = root_moves.iter().map(|_| AtomicUsize::new(1)).collect();
+    let next = AtomicUsize::new(0);
+
+    if depth > Depth(1) {
+        thread::scope(|s| {
+            for _ in 0..threads.clamp(1, root_moves.len().max(1)) {
+                s.spawn(|| {
+                    let mut pos = pos.clone();
+                    loop {
+                        let idx = next.fetch_add(1, Ordering::Relaxed);
+                        if idx >= root_moves.len() {
+                            break;
+                        }
+                        pos.do_move(root_moves[idx]);
+                        let nodes = if pos.variant().has_drops() {
+                            perft_hashed::<MAX_DROP_MOVES>(&mut pos, depth - 1, table)
+                        } else {
+                            perft_hashed::<MAX_MOVES>(&mut pos, depth - 1, table)
+                        };
+                        counts[idx].store(nodes, Ordering::Relaxed);
+                        pos.undo_move(root_moves[idx]);
+                    }
+                });
+            }
+        });
+    }
+
+    root_moves.into_iter()
+        .zip(counts.into_iter().map(AtomicUsize::into_inner))
+        .collect()
+}
+
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// perft_divide() prints the node count of each root move followed by the
+// total, searching with all the available threads.
+pub fn perft_divide(pos: &Position, depth: Depth) -> usize {
+
+    let start = Instant::now();
+    let table = PerftTable::new(64);
+    let divide = perft_split(pos, depth, default_threads(), &table);
+
+    for (m, nodes) in &divide {
+        println!("{}: {}", m.to_string(pos.is_chess960()), nodes);
+    }
+
+    let nodes = divide.iter().map(|(_, nodes)| nodes).sum();
+    let elapsed = start.elapsed().as_millis().max(1);
+    println!("\nNodes searched: {}", nodes);
+    println!("Time (ms): {}, nps: {}", elapsed, nodes as u128 * 1000 / elapsed);
+
+    nodes
+}
+
+// perft_cmd() is called when engine receives the "perft" command, either
+// "perft <depth>" on the current position or "perft <fen> <depth>", also
+// from the command line.
+pub fn perft_cmd(pos: &mut Position, args: &str) {
+
+    let (fen, depth) = match args.rsplit_once(char::is_whitespace) {
+        Some((fen, depth)) => (fen.trim(), depth),
+        None => ("", args),
+    };
+
+    let depth = match depth.parse() {
+        Ok(depth) => Depth(depth),
+        Err(_) => {
+            println!("Usage: perft [fen] <depth>");
+            return;
+        }
+    };
+
+    if !fen.is_empty() {
+        let fen = fen.trim_start_matches("fen").trim();
+        pos.set(fen, pos.is_chess960() || is_chess960_fen(fen));
+    }
+
+    perft_divide(pos, depth);
+}
+
+// run_perft_suite() checks the perft counts given in EPD lines of the form
+// "<fen> ;D1 20 ;D2 400 ...", up to 'max_depth', and returns the number of
+// mismatches. Chess960 positions are recognized by their castling rights.
+pub fn run_perft_suite(lines: &str, max_depth: i32) -> usize {
+
+    let mut pos = Position::new();
+    let table = PerftTable::new(64);
+    let threads = default_threads();
+    let mut mismatches = 0;
+    let mut checked = 0;
+
+    for line in lines.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split(';');
+        let fen = fields.next().unwrap().trim();
+        pos.set(fen, is_chess960_fen(fen));
+
+        for field in fields {
+            let mut tokens = field.split_whitespace();
+            let depth = tokens.next().and_then(|d| d.trim_start_matches('D').parse::<i32>().ok());
+            let expected = tokens.next().and_then(|n| n.parse::<usize>().ok());
+            let (depth, expected) = match (depth, expected) {
+                (Some(depth), Some(expected)) if depth <= max_depth => (depth, expected),
+                _ => continue,
+            };
+
+            let nodes: usize = perft_split(&pos, Depth(depth), threads, &table)
+                .iter().map(|(_, nodes)| nodes).sum();
+            checked += 1;
+
+            if nodes != expected {
+                mismatches += 1;
+                println!("Mismatch: {} depth {} expected {} got {}", fen, depth, expected, nodes);
+            }
+        }
+    }
+
+    println!("Perft suite: {} counts checked, {} mismatches", checked, mismatches);
+    mismatches
+}
+
+// perft_suite() is called when engine receives the "perftsuite" command,
+// e.g. "perftsuite perftsuite.epd 5".
+pub fn perft_suite(args: &str) {
+
+    let mut tokens = args.split_whitespace();
+    let file = tokens.next().unwrap_or("");
+    let max_depth = tokens.next().and_then(|d| d.parse().ok()).unwrap_or(i32::MAX);
+
+    match fs::read_to_string(file) {
+        Ok(lines) => { run_perft_suite(&lines, max_depth); }
+        Err(e) => println!("Unable to read {}: {}", file, e),
+    }
+}
+
 #[cfg(test)]
 mod perft_test {
 
     use crate::types::score::Depth;
-    use super::perft;
+    use super::*;
     use crate::position::Position;
 
     struct PerftInfo {
@@ -195,4 +417,177 @@ mod perft_test {
 
     }
 
+    #[test]
+    fn perft_split_matches_perft() {
+        let mut pos = Position::new();
+        let table = PerftTable::new(16);
+
+        for (fen, depth) in [
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5),
+            ("r1k1r2q/p1ppp1pp/8/8/8/8/P1PPP1PP/R1K1R2Q w KQkq - 0 1", 3),
+        ] {
+            pos.set(fen, fen.starts_with("r1k1"));
+            let expected = perft::<false>(&mut pos, Depth(depth));
+            for threads in [1, 4] {
+                let nodes: usize = perft_split(&pos, Depth(depth), threads, &table)
+                    .iter().map(|(_, nodes)| nodes).sum();
+                assert_eq!(nodes, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn perft_suite_reports_mismatches() {
+        let suite = "\
+            rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ;D1 20 ;D2 400 ;D3 8902 ;D4 197281\n\
+            8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ;D1 14 ;D2 191 ;D3 2812 ;D4 43238\n\
+            bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9 ;D1 21 ;D2 528 ;D3 12189\n";
+
+        assert_eq!(run_perft_suite(suite, 4), 0);
+        assert_eq!(run_perft_suite(&suite.replace("D4 197281", "D4 197280"), 4), 1);
+        assert_eq!(run_perft_suite(&suite.replace("D4 197281", "D4 197280"), 3), 0);
+    }
+
+    #[test]
+    fn perft_chess960_suite() {
+        assert_eq!(run_perft_suite(include_str!("perft/chess960.epd"), 3), 0);
+    }
+
+    // perft_filtered() counts the nodes by filtering the pseudo-legal moves
+    // through Position::legal(), as a reference for the legal generator.
+    fn perft_filtered(pos: &mut Position, depth: i32) -> usize {
+        let mut nodes = 0;
+        for m in &pos.pseudo_legal_moves() {
+            if !pos.legal(m) {
+                continue;
+            }
+            if depth == 1 {
+                nodes += 1;
+            } else {
+                pos.do_move(m);
+                nodes += perft_filtered(pos, depth - 1);
+                pos.undo_move(m);
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    fn perft_legal_generator_matches_filtered() {
+        use crate::variant::Variant;
+
+        // Pins, checks, en passant and castling for both colors, and the
+        // variants sharing the legality of standard chess.
+        let perft_data = [
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3, Variant::Chess, false),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, Variant::Chess, false),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 4, Variant::Chess, false),
+            ("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1", 4, Variant::Chess, false),
+            ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3, Variant::Chess, false),
+            ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 5, Variant::Chess, false),
+            ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 5, Variant::Chess, false),
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 3, Variant::Chess, true),
+            ("2k5/8/8/8/8/8/8/4K3[Qn] w - - 0 1", 3, Variant::Crazyhouse, false),
+            ("r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1", 3, Variant::Crazyhouse, false),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 1+1 0 1", 3, Variant::ThreeCheck, false),
+            ("8/8/8/4k3/8/8/3PK3/8 w - - 0 1", 4, Variant::KingOfTheHill, false),
+        ];
+
+        let mut pos = Position::new();
+        for (fen, depth, variant, chess960) in perft_data {
+            pos.set_variant(variant, fen, chess960);
+            assert_eq!(perft::<false>(&mut pos, Depth(depth)), perft_filtered(&mut pos, depth), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn perft_crazyhouse() {
+        use crate::variant::Variant;
+
+        // Node counts from the test suite of the shakmaty crate. Drops from
+        // the start position first occur at ply 5.
+        let perft_data = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1", 5, 4888832),
+            ("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", 2, 75353),
+            ("2k5/8/8/8/8/8/8/4K3[Qn] w - - 0 1", 4, 932554),
+            ("r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1", 3, 58057),
+            ("4k3/1Q~6/8/8/4b3/8/Kpp5/8/ b - - 0 1", 4, 132758),
+        ];
+
+        let mut pos = Position::new();
+        for (fen, depth, nodes) in perft_data {
+            pos.set_variant(Variant::Crazyhouse, fen, false);
+            assert_eq!(perft::<false>(&mut pos, Depth(depth)), nodes, "{}", fen);
+        }
+
+        pos.set_variant(Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", false);
+        assert_eq!(pos.legal_moves().len(), 301);
+    }
+
+    #[test]
+    fn perft_atomic() {
+        use crate::variant::Variant;
+
+        // Node counts from the test suite of the shakmaty crate, the last
+        // three positions test castling in atomic Chess960.
+        let perft_data = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4, 197326, false),
+            ("rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1", 4, 1434825, false),
+            ("rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1", 4, 714499, false),
+            ("8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1", 4, 61401, true),
+            ("r3k1rR/5K2/8/8/8/8/8/8 b kq - 0 1", 4, 98729, true),
+            ("Rr2k1rR/3K4/3p4/8/8/8/7P/8 w kq - 0 1", 4, 241478, true),
+        ];
+
+        let mut pos = Position::new();
+        for (fen, depth, nodes, chess960) in perft_data {
+            pos.set_variant(Variant::Atomic, fen, chess960);
+            assert_eq!(perft::<false>(&mut pos, Depth(depth)), nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn perft_three_check() {
+        use crate::variant::Variant;
+
+        // Node counts from the test suite of the shakmaty crate. One check
+        // is left to each side, and the game is over after it.
+        let perft_data = [
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+1 0 1", 3, 97848),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 1+1 0 1", 3, 13410),
+        ];
+
+        let mut pos = Position::new();
+        for (fen, depth, nodes) in perft_data {
+            pos.set_variant(Variant::ThreeCheck, fen, false);
+            assert_eq!(perft::<false>(&mut pos, Depth(depth)), nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn perft_antichess() {
+        use crate::variant::Variant;
+
+        // Node counts from the test suite of the shakmaty crate. The pawn
+        // races promote, also to kings, and end when a side runs out of
+        // pieces.
+        let perft_data = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1", 4, 153299),
+            ("8/1p6/8/8/8/8/P7/8 w - - 0 1", 5, 1),
+            ("8/1p6/8/8/8/8/P7/8 w - - 0 1", 6, 0),
+            ("8/2p5/8/8/8/8/P7/8 w - - 0 1", 12, 2557),
+        ];
+
+        let mut pos = Position::new();
+        for (fen, depth, nodes) in perft_data {
+            pos.set_variant(Variant::Antichess, fen, false);
+            assert_eq!(perft::<false>(&mut pos, Depth(depth)), nodes, "{}", fen);
+        }
+
+        // Castling rights are ignored in antichess
+        pos.set_variant(Variant::Antichess, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false);
+        assert_eq!(pos.fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1");
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,557 @@
+#[cfg(test)]
+mod pgn_test;
+
+use crate::types::r#move::Move;
+use crate::types::score::{Depth, Value};
+use crate::position::Position;
+use crate::uci::START_FEN;
+use crate::variant::Variant;
+
+use std::fs;
+use std::path::Path;
+
+// Maximum line length of the movetext written by PgnGame::to_pgn()
+const MAX_LINE_LENGTH: usize = 80;
+
+/// A move of a PGN game, together with its annotations. Variations hold the
+/// alternative lines to this move, starting from the same position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnMove {
+    pub m: Move,
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    pub eval: Option<(Value, Depth)>,
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    /// new() creates an annotation-free PGN move. The move must be legal in
+    /// the given position, which is used to compute its SAN.
+    pub fn new(pos: &mut Position, m: Move) -> PgnMove {
+        PgnMove {
+            m,
+            san: pos.move_to_san(m),
+            nags: Vec::new(),
+            comment: None,
+            eval: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    pub result: String,
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PgnGame {
+    pub fn new() -> PgnGame {
+        PgnGame {
+            tags: Vec::new(),
+            comment: None,
+            moves: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// start_fen() returns the FEN of the initial position of the game, as
+    /// given by the FEN tag or the standard starting position.
+    pub fn start_fen(&self) -> &str {
+        self.tag("FEN").unwrap_or(START_FEN)
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.tag("Variant").is_some_and(|v| {
+            let v = v.to_lowercase();
+            v.contains("960") || v.contains("fischer")
+        })
+    }
+
+    /// variant() returns the variant given by the Variant tag, standard
+    /// chess if the tag is missing or names Chess960.
+    pub fn variant(&self) -> Variant {
+        self.tag("Variant").and_then(Variant::from_name).unwrap_or_default()
+    }
+
+    /// start_position() sets up the initial position of the game
+    pub fn start_position(&self) -> Position {
+        let mut pos = Position::new();
+        pos.set_variant(self.variant(), self.start_fen(), self.is_chess960());
+        pos
+    }
+
+    /// positions() replays the main line and returns the positions before
+    /// each move, followed by the final position.
+    pub fn positions(&self) -> Vec<Position> {
+        let mut pos = self.start_position();
+        let mut positions = Vec::with_capacity(self.moves.len() + 1);
+        for pgn_move in &self.moves {
+            positions.push(pos.clone());
+            pos.do_move(pgn_move.m);
+        }
+        positions.push(pos);
+        positions
+    }
+
+    /// push() appends a move played in the given position, which must be the
+    /// current final position of the main line.
+    pub fn push(&mut self, pos: &mut Position, m: Move) -> &mut PgnMove {
+        self.moves.push(PgnMove::new(pos, m));
+        self.moves.last_mut().unwrap()
+    }
+
+    /// to_pgn() writes the game in export format: the tags, followed by the
+    /// movetext with comments, NAGs, variations and {eval/depth} comments.
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        for (name, value) in &self.tags {
+            let value = if name == "Result" { &self.result } else { value };
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        if self.tag("Result").is_none() {
+            pgn.push_str(&format!("[Result \"{}\"]\n", self.result));
+        }
+        pgn.push('\n');
+
+        let pos = self.start_position();
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        write_line(&self.moves, pos.game_ply(), &mut tokens);
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push_str("\n\n");
+        pgn
+    }
+}
+
+// write_line() appends the movetext tokens of a line starting at the given
+// game ply. Move numbers are written before white moves, and before black
+// moves that start a line or follow a comment or a variation.
+fn write_line(moves: &[PgnMove], mut game_ply: i32, tokens: &mut Vec<String>) {
+    let mut need_number = true;
+
+    for pgn_move in moves {
+        let move_number = 1 + game_ply / 2;
+        let white = game_ply % 2 == 0;
+        if white {
+            tokens.push(format!("{}. {}", move_number, pgn_move.san));
+        } else if need_number {
+            tokens.push(format!("{}... {}", move_number, pgn_move.san));
+        } else {
+            tokens.push(pgn_move.san.clone());
+        }
+        need_number = false;
+
+        for nag in &pgn_move.nags {
+            tokens.push(format!("${}", nag));
+        }
+
+        if pgn_move.eval.is_some() || pgn_move.comment.is_some() {
+            let mut comment = String::new();
+            if let Some((value, depth)) = pgn_move.eval {
+                comment.push_str(&format!("{}/{}", format_eval(value), depth.0));
+            }
+            if let Some(text) = &pgn_move.comment {
+                if !comment.is_empty() {
+                    comment.push(' ');
+                }
+                comment.push_str(text);
+            }
+            tokens.push(format!("{{{}}}", comment));
+            need_number = true;
+        }
+
+        for variation in &pgn_move.variations {
+            let mut var_tokens = Vec::new();
+            write_line(variation, game_ply, &mut var_tokens);
+            if let Some(first) = var_tokens.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = var_tokens.last_mut() {
+                last.push(')');
+            }
+            tokens.append(&mut var_tokens);
+            need_number = true;
+        }
+
+        game_ply += 1;
+    }
+}
+
+// format_eval() writes a score in pawns, or as a mate distance in moves,
+// e.g. +0.35 or -M4.
+fn format_eval(value: Value) -> String {
+    let sign = if value < Value::ZERO { '-' } else { '+' };
+    let v = value.abs();
+    if v >= Value::MATE_IN_MAX_PLY {
+        format!("{}M{}", sign, (Value::MATE.0 - v.0 + 1) / 2)
+    } else {
+        format!("{}{}.{:02}", sign, v.0 / 100, v.0 % 100)
+    }
+}
+
+// parse_eval() is the inverse of format_eval() for a "score/depth" comment
+// prefix. It returns the evaluation and the rest of the comment.
+fn parse_eval(comment: &str) -> Option<((Value, Depth), String)> {
+    let (head, rest) = match comment.find(char::is_whitespace) {
+        Some(idx) => (&comment[..idx], comment[idx..].trim()),
+        None => (comment, ""),
+    };
+    let (score, depth) = head.split_once('/')?;
+    let depth = Depth(depth.parse().ok()?);
+    let negative = score.starts_with('-');
+    let score = score.trim_start_matches(['+', '-']);
+
+    let value = if let Some(moves) = score.strip_prefix('M') {
+        // A side mating in n moves is 2n - 1 plies from mate, a mated side 2n
+        let moves: i32 = moves.parse().ok()?;
+        Value::MATE - if negative { 2 * moves } else { 2 * moves - 1 }
+    } else {
+        let (pawns, cents) = score.split_once('.')?;
+        if cents.len() != 2 {
+            return None;
+        }
+        Value(pawns.parse::<i32>().ok()? * 100 + cents.parse::<i32>().ok()?)
+    };
+
+    Some(((if negative { -value } else { value }, depth), rest.to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    Result(String),
+    San(String),
+}
+
+// tokenize() splits a PGN text into tags, comments, NAGs, variation
+// delimiters, game results and SAN moves. Move numbers, escape lines and
+// rest-of-line comments are dropped.
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut line_start = true;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\n' {
+            line_start = true;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '%' && line_start {
+            while i < chars.len() && chars[i] != '\n' { i += 1; }
+            continue;
+        }
+        line_start = false;
+
+        match c {
+            ';' => {
+                while i < chars.len() && chars[i] != '\n' { i += 1; }
+            }
+            '{' => {
+                let start = i + 1;
+                while i < chars.len() && chars[i] != '}' { i += 1; }
+                if i == chars.len() {
+                    return Err("unterminated comment".to_string());
+                }
+                let comment: String = chars[start..i].iter().collect();
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+                i += 1;
+            }
+            '[' => {
+                let start = i + 1;
+                let mut in_string = false;
+                while i < chars.len() && (in_string || chars[i] != ']') {
+                    if chars[i] == '\\' && in_string {
+                        i += 1;
+                    } else if chars[i] == '"' {
+                        in_string = !in_string;
+                    }
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err("unterminated tag".to_string());
+                }
+                let tag: String = chars[start..i].iter().collect();
+                let tag = tag.trim();
+                let (name, value) = tag.split_once(char::is_whitespace)
+                    .ok_or(format!("invalid tag [{}]", tag))?;
+                let value = value.trim().trim_start_matches('"').trim_end_matches('"');
+                tokens.push(Token::Tag(name.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")));
+                i += 1;
+            }
+            '(' => { tokens.push(Token::Open); i += 1; }
+            ')' => { tokens.push(Token::Close); i += 1; }
+            '$' => {
+                let start = i + 1;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() { i += 1; }
+                let nag: String = chars[start..i].iter().collect();
+                tokens.push(Token::Nag(nag.parse().map_err(|_| format!("invalid NAG ${}", nag))?));
+            }
+            _ => {
+                let start = i;
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !"{}()[];$".contains(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokenize_word(&word, &mut tokens);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// tokenize_word() handles a symbol token: a game result, a move number
+// possibly glued to a move (12.e4), or a SAN move with suffix annotations.
+fn tokenize_word(word: &str, tokens: &mut Vec<Token>) {
+    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&word) {
+        tokens.push(Token::Result(word.to_string()));
+        return;
+    }
+
+    let digits = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    let word = if digits.len() < word.len() && digits.starts_with('.') {
+        digits.trim_start_matches('.')
+    } else {
+        word
+    };
+    if word.is_empty() {
+        return;
+    }
+
+    // Suffix annotations are converted to the equivalent NAGs
+    let san = word.trim_end_matches(['!', '?']);
+    tokens.push(Token::San(san.to_string()));
+    let nag = match &word[san.len()..] {
+        "!" => 1,
+        "?" => 2,
+        "!!" => 3,
+        "??" => 4,
+        "!?" => 5,
+        "?!" => 6,
+        _ => return,
+    };
+    tokens.push(Token::Nag(nag));
+}
+
+// parse_line() parses the moves of a line from the given position, up to the
+// end of the variation or of the game. The position is restored on return.
+fn parse_line(
+    pos: &mut Position, tokens: &[Token], idx: &mut usize, game: &mut PgnGame, top_level: bool
+) -> Result<Vec<PgnMove>, String> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+
+    let result = loop {
+        if *idx == tokens.len() {
+            break Ok(());
+        }
+
+        match &tokens[*idx] {
+            Token::San(san) => {
+                let m = pos.parse_san(san);
+                if m == Move::NONE {
+                    break Err(format!("illegal move {} in position {}", san, pos.fen()));
+                }
+                moves.push(PgnMove::new(pos, m));
+                pos.do_move(m);
+            }
+            Token::Nag(nag) => {
+                if let Some(last) = moves.last_mut() {
+                    last.nags.push(*nag);
+                }
+            }
+            Token::Comment(comment) => match moves.last_mut() {
+                Some(last) => {
+                    let (eval, text) = match parse_eval(comment) {
+                        Some((eval, text)) => (Some(eval), text),
+                        None => (None, comment.clone()),
+                    };
+                    last.eval = last.eval.or(eval);
+                    if !text.is_empty() {
+                        last.comment = Some(match last.comment.take() {
+                            Some(prev) => format!("{} {}", prev, text),
+                            None => text,
+                        });
+                    }
+                }
+                None if top_level => game.comment = Some(comment.clone()),
+                None => {}
+            },
+            Token::Open => {
+                let last = match moves.last() {
+                    Some(last) => last.m,
+                    None => break Err("variation without a preceding move".to_string()),
+                };
+                *idx += 1;
+                pos.undo_move(last);
+                let variation = parse_line(pos, tokens, idx, game, false);
+                pos.do_move(last);
+                match variation {
+                    Ok(variation) => moves.last_mut().unwrap().variations.push(variation),
+                    Err(e) => break Err(e),
+                }
+            }
+            Token::Close => {
+                if top_level {
+                    break Err("unmatched ')'".to_string());
+                }
+                break Ok(());
+            }
+            Token::Result(result) => {
+                if !top_level {
+                    break Err("result inside a variation".to_string());
+                }
+                game.result = result.clone();
+                break Ok(());
+            }
+            Token::Tag(..) => {
+                if !top_level {
+                    break Err("unterminated variation".to_string());
+                }
+                // A new game starts without a result for the previous one
+                *idx -= 1;
+                break Ok(());
+            }
+        }
+        *idx += 1;
+    };
+
+    for pgn_move in moves.iter().rev() {
+        pos.undo_move(pgn_move.m);
+    }
+
+    result.map(|_| moves)
+}
+
+// skip_game() moves past the rest of a game after an error, up to its
+// result or to the tags of the next game.
+fn skip_game(tokens: &[Token], idx: &mut usize) {
+    while let Some(token) = tokens.get(*idx) {
+        match token {
+            Token::Tag(..) => return,
+            Token::Result(_) => {
+                *idx += 1;
+                return;
+            }
+            _ => *idx += 1,
+        }
+    }
+}
+
+/// parse_pgn_games() parses all the games of a PGN text, returning the
+/// result of each game, so that a game with an illegal or unparsable move
+/// is skipped and the next ones are still read. Only an unterminated
+/// comment or tag, which swallows the rest of the text, fails the whole
+/// text.
+pub fn parse_pgn_games(text: &str) -> Result<Vec<Result<PgnGame, String>>, String> {
+    let tokens = tokenize(text)?;
+    let mut games = Vec::new();
+    let mut idx = 0;
+
+    while idx < tokens.len() {
+        let mut game = PgnGame::new();
+
+        while let Some(Token::Tag(name, value)) = tokens.get(idx) {
+            game.tags.push((name.clone(), value.clone()));
+            idx += 1;
+        }
+
+        let mut pos = game.start_position();
+        match parse_line(&mut pos, &tokens, &mut idx, &mut game, true) {
+            Ok(moves) => game.moves = moves,
+            Err(e) => {
+                skip_game(&tokens, &mut idx);
+                games.push(Err(format!("game {}: {}", games.len() + 1, e)));
+                continue;
+            }
+        }
+        idx += 1;
+
+        if let Some(result) = game.tag("Result") {
+            if game.result == "*" {
+                game.result = result.to_string();
+            }
+        }
+        games.push(Ok(game));
+    }
+
+    Ok(games)
+}
+
+/// parse_pgn() parses all the games of a PGN text. Movetext is validated
+/// against the rules, so an illegal or ambiguous move is an error.
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, String> {
+    parse_pgn_games(text)?.into_iter().collect()
+}
+
+/// read_pgn_games() reads a PGN file and parses its games one by one, see
+/// parse_pgn_games().
+pub fn read_pgn_games<P: AsRef<Path>>(path: P) -> Result<Vec<Result<PgnGame, String>>, String> {
+    let bytes = fs::read(&path)
+        .map_err(|e| format!("cannot read {}: {}", path.as_ref().display(), e))?;
+    parse_pgn_games(&String::from_utf8_lossy(&bytes))
+}
+
+/// read_pgn_file() reads and parses all the games of a PGN file
+pub fn read_pgn_file<P: AsRef<Path>>(path: P) -> Result<Vec<PgnGame>, String> {
+    read_pgn_games(path)?.into_iter().collect()
+}
+
+/// write_pgn_file() writes the given games to a file in export format
+pub fn write_pgn_file<P: AsRef<Path>>(path: P, games: &[PgnGame]) -> Result<(), String> {
+    let text: String = games.iter().map(|game| game.to_pgn()).collect();
+    fs::write(&path, text)
+        .map_err(|e| format!("cannot write {}: {}", path.as_ref().display(), e))
+}
@@ -0,0 +1,94 @@
+use super::*;
+use crate::types::square::Square;
+
+const SAMPLE: &str = r#"[Event "Casual Game"]
+[Site "Berlin GER"]
+[Date "1852.??.??"]
+[White "Adolf Anderssen"]
+[Black "Jean Dufresne"]
+[Result "1-0"]
+
+1.e4 e5 2.Nf3 Nc6 3.Bc4 Bc5 4.b4 Bxb4 5.c3 Ba5 6.d4 exd4 7.O-O d3 8.Qb3 Qf6
+9.e5 Qg6 10.Re1 Nge7 11.Ba3 b5 12.Qxb5 Rb8 13.Qa4 Bb6 14.Nbd2 Bb7 15.Ne4 Qf5
+16.Bxd3 Qh5 17.Nf6+ gxf6 18.exf6 Rg8 19.Rad1 Qxf3 20.Rxe7+ Nxe7 21.Qxd7+ Kxd7
+22.Bf5+ Ke8 23.Bd7+ Kf8 24.Bxe7# 1-0
+
+[Event "Annotated"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1"]
+[Result "*"]
+
+{Black to move} 1... Kd7 $1 2. e4 {+1.20/18 passed pawn} (2. Kd2 Ke6 (2... Kd6) 3. e4) 2... Ke6?! *
+"#;
+
+#[test]
+fn parse_games_with_tags_and_annotations() {
+    let games = parse_pgn(SAMPLE).unwrap();
+    assert_eq!(games.len(), 2);
+
+    let evergreen = &games[0];
+    assert_eq!(evergreen.tag("White"), Some("Adolf Anderssen"));
+    assert_eq!(evergreen.result, "1-0");
+    assert_eq!(evergreen.moves.len(), 47);
+    assert_eq!(evergreen.moves[12].san, "O-O");
+    assert_eq!(evergreen.moves[46].san, "Bxe7#");
+    assert_eq!(evergreen.moves[46].m, Move::make(Square::A3, Square::E7));
+
+    let positions = evergreen.positions();
+    assert_eq!(positions.len(), 48);
+    assert_eq!(positions[47].fen(), "1r3kr1/pbpBBp1p/1b3P2/8/8/2P2q2/P4PPP/3R2K1 b - - 0 24");
+
+    let annotated = &games[1];
+    assert_eq!(annotated.comment.as_deref(), Some("Black to move"));
+    assert_eq!(annotated.moves.len(), 3);
+    assert_eq!(annotated.moves[0].nags, vec![1]);
+    assert_eq!(annotated.moves[1].eval, Some((Value(120), Depth(18))));
+    assert_eq!(annotated.moves[1].comment.as_deref(), Some("passed pawn"));
+    assert_eq!(annotated.moves[1].variations.len(), 1);
+    assert_eq!(annotated.moves[1].variations[0].len(), 3);
+    assert_eq!(annotated.moves[1].variations[0][1].variations[0][0].san, "Kd6");
+    assert_eq!(annotated.moves[2].nags, vec![6]);
+}
+
+#[test]
+fn written_games_parse_back_identically() {
+    let games = parse_pgn(SAMPLE).unwrap();
+    for game in &games {
+        let pgn = game.to_pgn();
+        let reparsed = parse_pgn(&pgn).unwrap();
+        assert_eq!(reparsed.len(), 1);
+        assert_eq!(&reparsed[0], game, "{}", pgn);
+    }
+
+    let pgn = games[1].to_pgn().split_whitespace().collect::<Vec<_>>().join(" ");
+    assert!(pgn.contains("{Black to move} 1... Kd7 $1 2. e4 {+1.20/18 passed pawn} (2. Kd2 Ke6 (2... Kd6) 3. e4) 2... Ke6 $6 *"), "{}", pgn);
+}
+
+#[test]
+fn eval_comments_round_trip() {
+    for value in [Value(0), Value(35), Value(-1234), Value::MATE - 5, -Value::MATE + 8] {
+        let comment = format!("{}/7", format_eval(value));
+        assert_eq!(parse_eval(&comment), Some(((value, Depth(7)), String::new())), "{}", comment);
+    }
+}
+
+#[test]
+fn illegal_moves_are_rejected() {
+    assert!(parse_pgn("1. e4 e5 2. Ke3 *").is_err());
+    assert!(parse_pgn("1. e4 (1. d4 *").is_err());
+}
+
+#[test]
+fn bad_games_are_skipped() {
+    let text = "[Event \"a\"]\n1. e4 e5 1-0\n\n\
+                [Event \"b\"]\n1. e4 e5 2. Ke3 Nc6 0-1\n\n\
+                [Event \"c\"]\n1. d4 (1. e4 d5\n\n\
+                [Event \"d\"]\n1. d4 d5 1/2-1/2\n";
+    let games = parse_pgn_games(text).unwrap();
+
+    assert_eq!(games.len(), 4);
+    assert!(games[1].as_ref().unwrap_err().starts_with("game 2: illegal move Ke3"));
+    assert!(games[2].is_err());
+    assert_eq!(games[3].as_ref().unwrap().tag("Event"), Some("d"));
+    assert_eq!(games[3].as_ref().unwrap().result, "1/2-1/2");
+    assert!(parse_pgn(text).is_err());
+}
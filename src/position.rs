@@ -1,6 +1,11 @@
 pub mod inline;
 pub mod fen;
 pub mod game;
+pub mod san;
+pub mod epd;
+pub mod cuckoo;
+pub mod chess960;
+pub mod atomic;
 
 
 use crate::attacks::attack_bb::*;
@@ -11,6 +16,7 @@ use crate::types::bitboard::*;
 use crate::types::score::*;
 use crate::zobrist::*;
 use crate::psqt;
+use crate::variant::Variant;
 //use crate::rng;
 //use self::inline::*;
 
@@ -22,11 +28,14 @@ pub struct StateInfo {
     pub plies_from_null: i32,
     pub psq: Score,
     pub ep_square: Square,
+    pub material_key: Key,
+    pub checks_given: [u8; COLOR_NB],
 
     // Not copied when making a move (will be recomputed anyhow)
     pub key: Key,
     pub checkers_bb: Bitboard,
     pub captured_piece: Piece,
+    pub captured_promoted: bool,
     pub blockers_for_king: [Bitboard; COLOR_NB],
     pub pinners: [Bitboard; COLOR_NB],
     pub check_squares: [Bitboard; PIECE_TYPE_NB],
@@ -41,9 +50,12 @@ impl StateInfo {
             plies_from_null: 0,
             psq: Score::ZERO,
             ep_square: Square::NONE,
+            material_key: KEY_ZERO,
+            checks_given: [0; COLOR_NB],
             key: KEY_ZERO,
             checkers_bb: EMPTY_BB,
             captured_piece: NO_PIECE,
+            captured_promoted: false,
             blockers_for_king: [EMPTY_BB; COLOR_NB],
             pinners: [EMPTY_BB; COLOR_NB],
             check_squares: [EMPTY_BB; PIECE_TYPE_NB],
@@ -59,10 +71,13 @@ impl StateInfo {
             plies_from_null: self.plies_from_null,
             psq: self.psq,
             ep_square: self.ep_square,
+            material_key: self.material_key,
+            checks_given: self.checks_given,
             // Reset
             key: KEY_ZERO,
             checkers_bb: EMPTY_BB,
             captured_piece: NO_PIECE,
+            captured_promoted: false,
             blockers_for_king: [EMPTY_BB; COLOR_NB],
             pinners: [EMPTY_BB; COLOR_NB],
             check_squares: [EMPTY_BB; PIECE_TYPE_NB],
@@ -71,45 +86,54 @@ impl StateInfo {
     }
 }
 
+// Explosion holds the pieces blown up by an atomic capture, to be put back
+// by undo_move(). It is kept out of StateInfo so that the other variants do
+// not pay for copying it at every move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explosion {
+    pub blast: Bitboard,
+    pub exploded: [Piece; 9],
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Position {
-    pub zobrist: Zobrist,
     pub board: [Piece; SQUARE_NB],
     pub by_color_bb: [Bitboard; COLOR_NB],
     pub by_type_bb: [Bitboard; PIECE_TYPE_NB],
     pub piece_count: [i32; PIECE_NB],
+    pub in_hand: [i32; PIECE_NB],
+    pub promoted: Bitboard,
     pub castling_rights_mask: [CastlingRight; SQUARE_NB],
     pub castling_rook_square: [Square; CASTLING_RIGHT_NB],
     pub castling_path: [Bitboard; CASTLING_RIGHT_NB],
     pub game_ply: i32,
     pub side_to_move: Color,
     pub states: Vec<StateInfo>,
+    pub explosions: Vec<Explosion>,
     pub chess960: bool,
+    pub variant: Variant,
 }
 
 
 impl Position {
     pub fn new() -> Position {
-        let mut pos = Position {
-            zobrist: Zobrist::new(),
+        Position {
             board: [NO_PIECE; SQUARE_NB],
             by_color_bb: [EMPTY_BB; COLOR_NB],
             by_type_bb: [EMPTY_BB; PIECE_TYPE_NB],
             piece_count: [0; PIECE_NB],
+            in_hand: [0; PIECE_NB],
+            promoted: EMPTY_BB,
             castling_rights_mask: [NO_CASTLING; SQUARE_NB],
             castling_rook_square: [Square::NONE; CASTLING_RIGHT_NB],
             castling_path: [EMPTY_BB; CASTLING_RIGHT_NB],
             game_ply: 0,
             side_to_move: WHITE,
             states: Vec::new(),
+            explosions: Vec::new(),
             chess960: false,
-        };
-        pos.init();
-        pos
-    }
-
-    fn init(&mut self) {
-        self.zobrist.init();
+            variant: Variant::Chess,
+        }
     }
 
     /// FIX ME: reverse iterator for File
@@ -138,6 +162,8 @@ impl Position {
         self.by_color_bb = [EMPTY_BB; COLOR_NB];
         self.by_type_bb = [EMPTY_BB; PIECE_TYPE_NB];
         self.piece_count = [0; PIECE_NB];
+        self.in_hand = [0; PIECE_NB];
+        self.promoted = EMPTY_BB;
             
         self.castling_rights_mask = [NO_CASTLING; SQUARE_NB];
         self.castling_rook_square = [Square::NONE; CASTLING_RIGHT_NB];
@@ -145,7 +171,9 @@ impl Position {
         self.game_ply = 0;
         self.side_to_move = WHITE;
         self.states = Vec::new();
+        self.explosions = Vec::new();
         self.chess960 = false;
+        self.variant = Variant::Chess;
 
         self.init_states();
 
@@ -168,26 +196,59 @@ impl Position {
         
         self.st_mut().psq = Score::ZERO;
 
-        self.st_mut().checkers_bb = self.attackers_to(self.square(self.side_to_move, KING))
-            & self.pieces_c(self.opposite_side());
+        self.st_mut().checkers_bb = if self.variant.has_explosions() {
+            self.atomic_checkers(self.side_to_move)
+        } else if !self.variant.has_checks() {
+            EMPTY_BB
+        } else {
+            self.attackers_to(self.square(self.side_to_move, KING))
+                & self.pieces_c(self.opposite_side())
+        };
 
         self.set_check_info();
 
         for s in self.pieces() {
             let pc: Piece = self.piece_on(s);
-            self.st_mut().key ^= self.zobrist.psq[pc][s];
+            self.st_mut().key ^= ZOBRIST.psq[pc][s];
             self.st_mut().psq.0 += psqt::psq(pc, s).0;
         }
 
+        for pc in VALID_PIECES {
+            for n in 0..self.in_hand[pc] {
+                self.st_mut().key ^= ZOBRIST.in_hand[pc][n as usize];
+            }
+        }
+
+        for c in [WHITE, BLACK] {
+            for n in 0..self.st().checks_given[c] {
+                self.st_mut().key ^= ZOBRIST.checks[c][n as usize];
+            }
+        }
+
         if self.st().ep_square != Square::NONE {
-            self.st_mut().key ^= self.zobrist.en_passant[self.st().ep_square.file()];
+            self.st_mut().key ^= ZOBRIST.en_passant[self.st().ep_square.file()];
         }
 
         if self.side_to_move == BLACK {
-            self.st_mut().key ^= self.zobrist.side;
+            self.st_mut().key ^= ZOBRIST.side;
         }
 
-        self.st_mut().key ^= self.zobrist.castling[self.st().castling_right];
+        self.st_mut().key ^= ZOBRIST.castling[self.st().castling_right];
+
+        self.st_mut().material_key = self.compute_material_key();
+    }
+
+    // compute_material_key() returns the key of the material of the
+    // position, which hashes the number of pieces of each kind, not their
+    // squares.
+    pub fn compute_material_key(&self) -> Key {
+        let mut key = KEY_ZERO;
+        for pc in VALID_PIECES {
+            for cnt in 0..self.piece_count[pc] {
+                key ^= ZOBRIST.psq[pc][Square(cnt as u32)];
+            }
+        }
+        key
     }
 
     /// Position::set_check_info() sets king attacks to detect if a move gives check
@@ -195,11 +256,21 @@ impl Position {
         // self.st_mut().blockers_for_king[WHITE] = self.slider_blockers(self.pieces_c(BLACK), self.square(WHITE, KING), &mut self.pinners(BLACK));
         // self.st_mut().blockers_for_king[BLACK] = self.slider_blockers(self.pieces_c(WHITE), self.square(BLACK, KING), &mut self.pinners(WHITE));
         let mut pinners = EMPTY_BB;
-        self.st_mut().blockers_for_king[WHITE] = self.slider_blockers(self.pieces_c(BLACK), self.square(WHITE, KING), &mut pinners);
-        self.st_mut().pinners[WHITE] = pinners;
 
-        self.st_mut().blockers_for_king[BLACK] = self.slider_blockers(self.pieces_c(WHITE), self.square(BLACK, KING), &mut pinners);
-        self.st_mut().pinners[BLACK] = pinners;
+        // A king may be missing, e.g. after being blown up in atomic chess
+        if self.pieces_cp(WHITE, KING) != EMPTY_BB {
+            self.st_mut().blockers_for_king[WHITE] = self.slider_blockers(self.pieces_c(BLACK), self.square(WHITE, KING), &mut pinners);
+            self.st_mut().pinners[WHITE] = pinners;
+        }
+
+        if self.pieces_cp(BLACK, KING) != EMPTY_BB {
+            self.st_mut().blockers_for_king[BLACK] = self.slider_blockers(self.pieces_c(WHITE), self.square(BLACK, KING), &mut pinners);
+            self.st_mut().pinners[BLACK] = pinners;
+        }
+
+        if self.pieces_cp(!self.side_to_move, KING) == EMPTY_BB {
+            return;
+        }
 
         let ksq: Square = self.square(!self.side_to_move, KING);
 
@@ -329,6 +400,11 @@ impl Position {
         self.by_color_bb[pc.color()] ^= from_to_bb;
         self.board[from] = NO_PIECE;
         self.board[to] = pc;
+
+        // A promoted piece is still a pawn once captured in crazyhouse
+        if self.promoted & from != EMPTY_BB {
+            self.promoted ^= from_to_bb;
+        }
     }
 
     fn remove_piece(&mut self, s: Square) {
@@ -341,5 +417,17 @@ impl Position {
         self.piece_count[Piece::make(pc.color(), ALL_PIECES)] -= 1;
     }
 
+    // add_to_hand() and remove_from_hand() update the pieces in hand and
+    // return the change of the hash key.
+    fn add_to_hand(&mut self, pc: Piece) -> Key {
+        self.in_hand[pc] += 1;
+        ZOBRIST.in_hand[pc][self.in_hand[pc] as usize - 1]
+    }
+
+    fn remove_from_hand(&mut self, pc: Piece) -> Key {
+        self.in_hand[pc] -= 1;
+        ZOBRIST.in_hand[pc][self.in_hand[pc] as usize]
+    }
+
 
 }
\ No newline at end of file
@@ -0,0 +1,203 @@
+use super::*;
+
+impl Position {
+
+    // blast() returns the pieces blown up by a capture on square 's': the
+    // piece on 's' and the pieces but pawns around it.
+    pub fn blast(&self, s: Square) -> Bitboard {
+        (pseudo_attacks(KING, s) & self.pieces() & !self.pieces_p(PAWN)) | s
+    }
+
+    // atomic_attackers() returns the pieces of color 'c' attacking square
+    // 's' with the given occupancy. Nothing attacks a square next to the
+    // king of 'c', since capturing there would blow up that king too.
+    pub fn atomic_attackers(&self, s: Square, c: Color, occupied: Bitboard) -> Bitboard {
+        let kings = self.pieces_cp(c, KING);
+        if kings == EMPTY_BB || pseudo_attacks(KING, s) & kings != EMPTY_BB {
+            return EMPTY_BB;
+        }
+        self.attackers_to_occ(s, occupied) & self.pieces_c(c) & occupied
+    }
+
+    // atomic_checkers() returns the pieces giving check to the king of
+    // color 'c', if it is still on the board.
+    pub(super) fn atomic_checkers(&self, c: Color) -> Bitboard {
+        if self.pieces_cp(c, KING) == EMPTY_BB {
+            return EMPTY_BB;
+        }
+        self.atomic_attackers(self.square(c, KING), !c, self.pieces())
+    }
+
+    // atomic_removed() returns the squares emptied by the capture 'm': the
+    // blast around the destination square, the origin square and the
+    // square of a pawn captured en passant.
+    fn atomic_removed(&self, m: Move) -> Bitboard {
+        let mut removed = self.blast(m.to()) | m.from();
+        if m.move_type() == EN_PASSANT {
+            removed |= m.to() - pawn_push(self.side_to_move);
+        }
+        removed
+    }
+
+    // atomic_legal() tests whether a pseudo-legal move is legal in atomic
+    // chess. Kings cannot capture, a capture must not blow up our own king
+    // and blowing up the enemy king wins even when our king is in check.
+    pub(crate) fn atomic_legal(&self, m: Move) -> bool {
+        let us = self.side_to_move;
+        let from = m.from();
+        let to = m.to();
+        let ksq = self.square(us, KING);
+
+        if self.capture(m) {
+            if from == ksq {
+                return false;
+            }
+
+            let removed = self.atomic_removed(m);
+            if removed & ksq != EMPTY_BB {
+                return false;
+            }
+            if removed & self.pieces_cp(!us, KING) != EMPTY_BB {
+                return true;
+            }
+            return self.atomic_attackers(ksq, !us, self.pieces() & !removed) == EMPTY_BB;
+        }
+
+        // The king may not pass through an attacked square when castling.
+        // Its destination square is tested after the rook has moved.
+        if m.move_type() == CASTLING {
+            let kto = relative_square(us, if to > from { Square::G1 } else { Square::C1 });
+            let rto = relative_square(us, if to > from { Square::F1 } else { Square::D1 });
+            let step = if kto > from { EAST } else { WEST };
+
+            let mut s = from;
+            while s != kto {
+                if self.atomic_attackers(s, !us, self.pieces() ^ from) != EMPTY_BB {
+                    return false;
+                }
+                s += step;
+            }
+
+            let occupied = (self.pieces() ^ from ^ to) | kto | rto;
+            return self.atomic_attackers(kto, !us, occupied) == EMPTY_BB;
+        }
+
+        let ksq = if from == ksq { to } else { ksq };
+        self.atomic_attackers(ksq, !us, (self.pieces() ^ from) | to) == EMPTY_BB
+    }
+
+    // atomic_capture_gives_check() tests whether the capture 'm' leaves
+    // the enemy king in check from the pieces surviving the explosion.
+    pub(super) fn atomic_capture_gives_check(&self, m: Move) -> bool {
+        let them = !self.side_to_move;
+        let removed = self.atomic_removed(m);
+        if removed & self.pieces_cp(them, KING) != EMPTY_BB {
+            return false;
+        }
+        let ksq = self.square(them, KING);
+        self.atomic_attackers(ksq, self.side_to_move, self.pieces() & !removed) != EMPTY_BB
+    }
+
+    // kings_adjacent_after() tests whether the kings stand next to each
+    // other after the non-capture 'm', when neither can be checked.
+    pub(super) fn kings_adjacent_after(&self, m: Move) -> bool {
+        let us = self.side_to_move;
+        let ksq = if m.move_type() == CASTLING {
+            relative_square(us, if m.to() > m.from() { Square::G1 } else { Square::C1 })
+        } else if self.moved_piece(m).piece_type() == KING {
+            m.to()
+        } else {
+            self.square(us, KING)
+        };
+        pseudo_attacks(KING, ksq) & self.pieces_cp(!us, KING) != EMPTY_BB
+    }
+
+    // explode() removes the pieces blown up by a capture on square 's',
+    // the capturing piece included, and pushes them on the explosion stack
+    // to be put back by undo_move(). Returns the hash key change.
+    pub(super) fn explode(&mut self, s: Square) -> Key {
+        let blast = self.blast(s);
+        let mut exploded = [NO_PIECE; 9];
+        let mut k = KEY_ZERO;
+        let mut cr = NO_CASTLING;
+
+        for (i, sq) in blast.into_iter().enumerate() {
+            let pc = self.piece_on(sq);
+            exploded[i] = pc;
+            self.remove_piece(sq);
+            self.board[sq] = NO_PIECE;
+
+            k ^= ZOBRIST.psq[pc][sq];
+            let cnt = self.piece_count[pc];
+            self.st_mut().material_key ^= ZOBRIST.psq[pc][Square(cnt as u32)];
+            self.st_mut().psq -= psqt::psq(pc, sq);
+            cr |= self.castling_rights_mask[sq];
+        }
+        self.explosions.push(Explosion { blast, exploded });
+
+        // Exploded kings and rooks lose their castling rights
+        if self.st().castling_right & cr != NO_CASTLING {
+            k ^= ZOBRIST.castling[self.st().castling_right];
+            self.st_mut().castling_right &= !cr;
+            k ^= ZOBRIST.castling[self.st().castling_right];
+        }
+        k
+    }
+
+    // unexplode() puts back the pieces removed by the last explode()
+    pub(super) fn unexplode(&mut self) {
+        let Explosion { blast, exploded } = self.explosions.pop().unwrap();
+        for (i, sq) in blast.into_iter().enumerate() {
+            self.put_piece(exploded[i], sq);
+        }
+    }
+}
+
+#[cfg(test)]
+mod atomic_test {
+
+    use super::*;
+
+    fn atomic(fen: &str) -> Position {
+        let mut pos = Position::new();
+        pos.set_variant(Variant::Atomic, fen, false);
+        pos
+    }
+
+    #[test]
+    fn explosions() {
+        // The capturing pawn blows up with the knight next to its target,
+        // and rooks blown up lose their castling rights.
+        let data = [
+            ("4k3/8/2n5/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5", "4k3/8/8/8/8/8/8/4K3 b - - 0 1"),
+            ("r3k2r/1p6/8/8/8/8/8/R3K2R w KQkq - 0 1", "a1a8", "4k2r/1p6/8/8/8/8/8/4K2R b Kk - 0 1"),
+        ];
+
+        for (fen, uci, after) in data {
+            let mut pos = atomic(fen);
+            let m = Move::from_uci(&pos, uci).unwrap();
+            pos.do_move(m);
+            assert_eq!(pos.fen(), after);
+            assert_eq!(atomic(after).key(), pos.key());
+            assert_eq!(atomic(after).material_key(), pos.material_key());
+
+            pos.undo_move(m);
+            assert_eq!(pos.fen(), fen);
+        }
+
+        // Blowing up the enemy king wins, even when in check
+        let mut pos = atomic("4k3/3p4/8/8/8/8/3R4/r3K3 w - - 0 1");
+        assert!(pos.checkers() != EMPTY_BB);
+        let m = Move::from_uci(&pos, "d2d7").unwrap();
+        assert!(pos.legal_moves().contains(m));
+        pos.do_move(m);
+        assert!(pos.legal_moves().is_empty());
+        assert_eq!(pos.checkers(), EMPTY_BB);
+
+        // Kings cannot capture and adjacent kings cannot check each other
+        let pos = atomic("8/8/8/3k4/3Kq3/8/8/8 w - - 0 1");
+        assert_eq!(pos.checkers(), EMPTY_BB);
+        assert!(!pos.legal_moves().contains(Move::make(Square::D4, Square::E4)));
+        assert!(!pos.legal_moves().contains(Move::make(Square::D4, Square::D3)));
+    }
+}
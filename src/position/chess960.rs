@@ -0,0 +1,158 @@
+use super::*;
+
+// Number of Chess960 starting positions, and of Double Fischer Random
+// Chess positions where both sides have their own Chess960 setup.
+pub const CHESS960_NB: usize = 960;
+pub const DFRC_NB: usize = CHESS960_NB * CHESS960_NB;
+
+// Placements of the two knights on the five squares left once the bishops
+// and the queen are placed, following Scharnagl's numbering.
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4),
+];
+
+/// back_rank() returns the piece types of the back rank of the Chess960
+/// starting position with the given Scharnagl index, from file a to file h.
+/// Index 518 is the standard chess starting position.
+pub fn back_rank(idx: usize) -> [PieceType; 8] {
+    debug_assert!(idx < CHESS960_NB);
+
+    let mut rank = [NO_PIECE_TYPE; 8];
+    let mut n = idx;
+
+    // Light squared bishop on b, d, f or h, dark squared one on a, c, e or g
+    rank[2 * (n % 4) + 1] = BISHOP;
+    n /= 4;
+    rank[2 * (n % 4)] = BISHOP;
+    n /= 4;
+
+    let mut empty: Vec<usize> = (0..8).filter(|&f| rank[f] == NO_PIECE_TYPE).collect();
+    rank[empty.remove(n % 6)] = QUEEN;
+    n /= 6;
+
+    let (n1, n2) = KNIGHTS[n];
+    rank[empty[n1]] = KNIGHT;
+    rank[empty[n2]] = KNIGHT;
+
+    // The king stands between the two rooks on the remaining squares
+    let rest: Vec<usize> = (0..8).filter(|&f| rank[f] == NO_PIECE_TYPE).collect();
+    for (f, pt) in rest.into_iter().zip([ROOK, KING, ROOK]) {
+        rank[f] = pt;
+    }
+
+    rank
+}
+
+/// dfrc_fen() returns the FEN of the Double Fischer Random Chess starting
+/// position with the given back ranks for White and Black. Castling rights
+/// use X-FEN, which is unambiguous for starting positions.
+pub fn dfrc_fen(white: usize, black: usize) -> String {
+    let rank = |idx: usize, color: Color| -> String {
+        back_rank(idx).iter().map(|&pt| Piece::make(color, pt).to_char()).collect()
+    };
+
+    format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1", rank(black, BLACK), rank(white, WHITE))
+}
+
+/// chess960_fen() returns the FEN of the Chess960 starting position with
+/// the given Scharnagl index.
+pub fn chess960_fen(idx: usize) -> String {
+    dfrc_fen(idx, idx)
+}
+
+impl Position {
+
+    /// set_chess960() sets up the Chess960 starting position with the given
+    /// Scharnagl index.
+    pub fn set_chess960(&mut self, idx: usize) {
+        self.set(&chess960_fen(idx), true);
+    }
+
+    /// set_dfrc() sets up a Double Fischer Random Chess starting position.
+    /// Index 'idx' stands for the White back rank idx / 960 and the Black
+    /// back rank idx % 960.
+    pub fn set_dfrc(&mut self, idx: usize) {
+        debug_assert!(idx < DFRC_NB);
+        self.set(&dfrc_fen(idx / CHESS960_NB, idx % CHESS960_NB), true);
+    }
+}
+
+#[cfg(test)]
+mod chess960_test {
+
+    use super::*;
+    use crate::perft::perft;
+    use crate::position::fen::is_chess960_fen;
+    use crate::types::score::Depth;
+    use crate::uci::START_FEN;
+
+    use std::collections::HashSet;
+
+    #[test]
+    fn scharnagl_numbering() {
+        assert_eq!(chess960_fen(518), START_FEN);
+        assert!(chess960_fen(0).starts_with("bbqnnrkr/"));
+        assert!(chess960_fen(959).starts_with("rkrnnqbb/"));
+
+        let mut seen = HashSet::new();
+        for idx in 0..CHESS960_NB {
+            let rank = back_rank(idx);
+            let file = |pt: PieceType| rank.iter().position(|&p| p == pt).unwrap();
+            let rooks: Vec<usize> = (0..8).filter(|&f| rank[f] == ROOK).collect();
+
+            assert!(rooks[0] < file(KING) && file(KING) < rooks[1]);
+            assert!(rank.iter().enumerate().filter(|&(_, &pt)| pt == BISHOP).map(|(f, _)| f % 2).sum::<usize>() == 1);
+            assert!(seen.insert(chess960_fen(idx)));
+        }
+    }
+
+    #[test]
+    fn shredder_and_xfen_round_trip() {
+        let mut pos = Position::new();
+
+        for idx in 0..CHESS960_NB {
+            pos.set_chess960(idx);
+            let fen = pos.fen();
+            assert_eq!(pos.xfen(), chess960_fen(idx));
+
+            pos.set(&fen, true);
+            assert_eq!(pos.fen(), fen);
+            assert_eq!(pos.xfen(), chess960_fen(idx));
+        }
+
+        // An inner rook needs its file in X-FEN
+        pos.set("rk2r3/8/8/8/8/8/8/RK2R2R w EAe - 0 1", true);
+        assert_eq!(pos.xfen(), "rk2r3/8/8/8/8/8/8/RK2R2R w EQk - 0 1");
+        let xfen = pos.xfen();
+        pos.set(&xfen, true);
+        assert_eq!(pos.fen(), "rk2r3/8/8/8/8/8/8/RK2R2R w EAe - 0 1");
+
+        assert!(is_chess960_fen(&xfen));
+        assert!(is_chess960_fen(&chess960_fen(0)));
+        assert!(!is_chess960_fen(&chess960_fen(518)));
+        assert!(!is_chess960_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1"));
+    }
+
+    #[test]
+    fn perft_start_positions() {
+        let mut pos = Position::new();
+        let mut mirrored = Position::new();
+
+        pos.set_chess960(518);
+        assert_eq!(perft::<false>(&mut pos, Depth(4)), 197_281);
+
+        // Castling is possible at once with the king next to its rook
+        pos.set_chess960(3);
+        assert_eq!(pos.legal_moves().len(), 21);
+
+        // A position with White to move has the same perft counts as the
+        // color flipped one, with swapped back ranks and Black to move.
+        for idx in (0..CHESS960_NB).map(|idx| idx * (CHESS960_NB + 1)).chain((0..DFRC_NB).step_by(997)) {
+            let (white, black) = (idx / CHESS960_NB, idx % CHESS960_NB);
+            pos.set_dfrc(idx);
+            mirrored.set(&dfrc_fen(black, white).replace(" w ", " b "), true);
+            let nodes = perft::<false>(&mut pos, Depth(3));
+            assert_eq!(nodes, perft::<false>(&mut mirrored, Depth(3)), "{}", idx);
+        }
+    }
+}
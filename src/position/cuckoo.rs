@@ -0,0 +1,163 @@
+use super::*;
+
+use std::sync::OnceLock;
+
+// Marcel van Kervinck's cuckoo algorithm for fast detection of "upcoming
+// repetition" situations. Description of the algorithm in the following
+// paper: http://web.archive.org/web/20201107002606/https://marcelk.net/2013-04-06/paper/upcoming-rep-v2.pdf
+
+const CUCKOO_SIZE: usize = 8192;
+
+fn h1(h: Key) -> usize { (h & 0x1fff) as usize }
+fn h2(h: Key) -> usize { ((h >> 16) & 0x1fff) as usize }
+
+// Cuckoo holds the Zobrist key differences of all reversible moves, along
+// with the moves themselves, in a cuckoo hash table.
+pub struct Cuckoo {
+    keys: [Key; CUCKOO_SIZE],
+    moves: [Move; CUCKOO_SIZE],
+}
+
+impl Cuckoo {
+
+    fn new() -> Cuckoo {
+        let mut cuckoo = Cuckoo { keys: [KEY_ZERO; CUCKOO_SIZE], moves: [Move::NONE; CUCKOO_SIZE] };
+        let mut count = 0;
+
+        for pc in VALID_PIECES {
+            if pc.piece_type() == PAWN {
+                continue;
+            }
+            for s1 in VALID_SQUARES {
+                for s2 in VALID_SQUARES.into_iter().filter(|&s2| s2 > s1) {
+                    if pseudo_attacks(pc.piece_type(), s1) & s2 == 0 {
+                        continue;
+                    }
+
+                    let mut m = Move::make(s1, s2);
+                    let mut key = ZOBRIST.psq[pc][s1] ^ ZOBRIST.psq[pc][s2] ^ ZOBRIST.side;
+                    let mut i = h1(key);
+
+                    // Insert into the table, displacing the occupant to its
+                    // alternative slot until an empty one is found.
+                    loop {
+                        std::mem::swap(&mut cuckoo.keys[i], &mut key);
+                        std::mem::swap(&mut cuckoo.moves[i], &mut m);
+                        if m == Move::NONE {
+                            break;
+                        }
+                        i = if i == h1(key) { h2(key) } else { h1(key) };
+                    }
+                    count += 1;
+                }
+            }
+        }
+        debug_assert!(count == 3668);
+
+        cuckoo
+    }
+
+    fn probe(&self, key: Key) -> Option<Move> {
+        let j = h1(key);
+        if self.keys[j] == key {
+            return Some(self.moves[j]);
+        }
+        let j = h2(key);
+        if self.keys[j] == key {
+            return Some(self.moves[j]);
+        }
+        None
+    }
+}
+
+fn cuckoo() -> &'static Cuckoo {
+    static CUCKOO: OnceLock<Cuckoo> = OnceLock::new();
+    CUCKOO.get_or_init(Cuckoo::new)
+}
+
+impl Position {
+
+    /// upcoming_repetition() tests if the side to move has a move which draws
+    /// by repetition, or an earlier position has a move that directly reaches
+    /// the current position.
+    pub fn upcoming_repetition(&self, ply: i32) -> bool {
+
+        let end = std::cmp::min(self.st().rule50, self.st().plies_from_null);
+        if end < 3 {
+            return false;
+        }
+
+        let cuckoo = cuckoo();
+        let last = self.states.len() - 1;
+        let original_key = self.st().key;
+        let mut other = original_key ^ self.states[last - 1].key ^ ZOBRIST.side;
+
+        for i in (3..=end).step_by(2) {
+            let idx = last - i as usize;
+            other ^= self.states[idx + 1].key ^ self.states[idx].key ^ ZOBRIST.side;
+
+            if other != 0 {
+                continue;
+            }
+
+            if let Some(m) = cuckoo.probe(original_key ^ self.states[idx].key) {
+                let s1 = m.from();
+                let s2 = m.to();
+
+                if (between_bb(s1, s2) ^ s2) & self.pieces() == 0 {
+                    if ply > i {
+                        return true;
+                    }
+
+                    // For nodes before or at the root, check that the move
+                    // is a repetition rather than a move to the current
+                    // position, and that the position repeated before.
+                    let s = if self.empty(s1) { s2 } else { s1 };
+                    if self.piece_on(s).color() != self.side_to_move() {
+                        continue;
+                    }
+                    if self.states[idx].repetition != 0 {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod cuckoo_test {
+
+    use super::*;
+    use crate::uci::START_FEN;
+
+    fn play(pos: &mut Position, moves: &str) {
+        for m in moves.split_whitespace() {
+            let m = Move::from_uci(pos, m).unwrap();
+            pos.do_move(m);
+        }
+    }
+
+    #[test]
+    fn cuckoo_table_holds_reversible_moves() {
+        let cuckoo = cuckoo();
+        assert_eq!(cuckoo.moves.iter().filter(|&&m| m != Move::NONE).count(), 3668);
+    }
+
+    #[test]
+    fn detects_upcoming_repetition() {
+        let mut pos = Position::new();
+        pos.set(START_FEN, false);
+
+        play(&mut pos, "g1f3 g8f6 f3g1");
+        assert!(pos.upcoming_repetition(4));
+        assert!(!pos.upcoming_repetition(0));
+
+        // Only the side to move can repeat a position reached before the root
+        let mut pos = Position::new();
+        pos.set(START_FEN, false);
+        play(&mut pos, "e2e4 e7e5 f1c4 f8c5 c4f1 c5f8 g1f3");
+        assert!(!pos.upcoming_repetition(4));
+    }
+}
@@ -0,0 +1,147 @@
+use super::*;
+
+/// Epd holds an Extended Position Description record: the first four FEN
+/// fields followed by operations such as bm (best moves), am (avoid moves),
+/// id (identifier) or c0-c9 (comments). Operands are kept as written, SAN
+/// moves are resolved against the position by the caller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Epd {
+    pub fen: String,
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+
+    // parse() reads an EPD line, returning None if it does not contain the
+    // four position fields.
+    pub fn parse(line: &str) -> Option<Epd> {
+        let line = line.trim();
+        let mut fields = Vec::new();
+        let mut rest = line;
+
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return None;
+            }
+            fields.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+
+        let mut epd = Epd { fen: fields.join(" "), operations: Vec::new() };
+
+        // Operations are separated by semicolons, which may also appear
+        // inside quoted operands.
+        let mut operands: Vec<String> = Vec::new();
+        let mut token = String::new();
+        let mut in_string = false;
+
+        for c in rest.chars() {
+            match c {
+                '"' => {
+                    if in_string {
+                        operands.push(std::mem::take(&mut token));
+                    }
+                    in_string = !in_string;
+                }
+                ';' if !in_string => {
+                    if !token.is_empty() {
+                        operands.push(std::mem::take(&mut token));
+                    }
+                    if !operands.is_empty() {
+                        let opcode = operands.remove(0);
+                        epd.operations.push((opcode, std::mem::take(&mut operands)));
+                    }
+                }
+                c if c.is_whitespace() && !in_string => {
+                    if !token.is_empty() {
+                        operands.push(std::mem::take(&mut token));
+                    }
+                }
+                c => token.push(c),
+            }
+        }
+
+        // The last operation may lack its terminating semicolon
+        if !token.is_empty() {
+            operands.push(token);
+        }
+        if !operands.is_empty() {
+            let opcode = operands.remove(0);
+            epd.operations.push((opcode, operands));
+        }
+
+        Some(epd)
+    }
+
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id").and_then(|operands| operands.first()).map(|id| id.as_str())
+    }
+
+    // fen() returns the full FEN of the record, taking the move counters
+    // from the hmvc and fmvn operations if present.
+    pub fn full_fen(&self) -> String {
+        let counter = |opcode, default| self.operation(opcode)
+            .and_then(|operands| operands.first())
+            .map_or(default, |n| n.as_str());
+
+        format!("{} {} {}", self.fen, counter("hmvc", "0"), counter("fmvn", "1"))
+    }
+}
+
+impl Position {
+
+    /// set_epd() sets up the position described by an EPD record
+    pub fn set_epd(&mut self, epd: &Epd, is_chess960: bool) {
+        self.set(&epd.full_fen(), is_chess960);
+    }
+
+    /// epd() returns the four position fields of the FEN of the position
+    pub fn epd(&self) -> String {
+        self.fen().split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+    }
+
+    // parse_move() reads a move given either in SAN or in coordinate
+    // notation, as found in EPD operands.
+    pub fn parse_move(&self, s: &str) -> Move {
+        let m = self.parse_san(s);
+        if m != Move::NONE { m } else { Move::from_uci(self, s).unwrap_or(Move::NONE) }
+    }
+}
+
+#[cfg(test)]
+mod epd_test {
+
+    use super::*;
+
+    #[test]
+    fn parse_operations() {
+        let line = "1k1r4/pp1b1R2/3q2pp/4p3/2B5/4Q3/PPP2B2/2K5 b - - bm Qd1+; id \"BK.01\"; c0 \"a; b\";";
+        let epd = Epd::parse(line).unwrap();
+
+        assert_eq!(epd.fen, "1k1r4/pp1b1R2/3q2pp/4p3/2B5/4Q3/PPP2B2/2K5 b - -");
+        assert_eq!(epd.operation("bm"), Some(&["Qd1+".to_string()][..]));
+        assert_eq!(epd.id(), Some("BK.01"));
+        assert_eq!(epd.operation("c0"), Some(&["a; b".to_string()][..]));
+        assert_eq!(epd.operation("am"), None);
+
+        let mut pos = Position::new();
+        pos.set_epd(&epd, false);
+        assert_eq!(pos.epd(), epd.fen);
+        assert_eq!(pos.parse_move("Qd1+"), Move::make(Square::D6, Square::D1));
+        assert_eq!(pos.parse_move("d6d1"), Move::make(Square::D6, Square::D1));
+
+        let epd = Epd::parse("8/8/8/8/8/8/8/K6k w - - hmvc 12; fmvn 40; am Kb2 Kb1").unwrap();
+        assert_eq!(epd.full_fen(), "8/8/8/8/8/8/8/K6k w - - 12 40");
+        assert_eq!(epd.operation("am").unwrap().len(), 2);
+
+        assert!(Epd::parse("8/8/8/8 w").is_none());
+    }
+}
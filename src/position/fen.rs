@@ -1,24 +1,79 @@
 use super::*;
 
+// is_chess960_fen() tests whether the castling rights of a FEN can only be
+// those of a Chess960 position: Shredder-FEN or X-FEN file letters, or KQkq
+// with the king or the rook off its standard square.
+pub fn is_chess960_fen(fen: &str) -> bool {
+    let mut fields = fen.split_whitespace();
+    let board: Vec<Vec<char>> = fields.next().unwrap_or("").split('/').map(|rank| {
+        rank.chars().filter(|&c| c != '~').flat_map(|c| match c.to_digit(10) {
+            Some(d) => vec!['1'; d as usize],
+            None => vec![c],
+        }).collect()
+    }).collect();
+    let castling = fields.nth(1).unwrap_or("-");
+
+    let on = |file: usize, rank: usize, pc: char| {
+        board.get(7 - rank).and_then(|r| r.get(file)) == Some(&pc)
+    };
+
+    castling.chars().any(|c| match c {
+        'K' => !on(4, 0, 'K') || !on(7, 0, 'R'),
+        'Q' => !on(4, 0, 'K') || !on(0, 0, 'R'),
+        'k' => !on(4, 7, 'k') || !on(7, 7, 'r'),
+        'q' => !on(4, 7, 'k') || !on(0, 7, 'r'),
+        '-' => false,
+        _ => true,
+    })
+}
+
 impl Position {
 
     // set() initializes the position objection with the given FEN string.
     // This function is not very robust - make sure that input FENs are
     // correct. This is assumed to be the responsibility of the GUI.
     pub fn set(&mut self, fen_str: &str, is_chess960: bool) {
+        self.set_variant(Variant::Chess, fen_str, is_chess960);
+    }
+
+    // set_variant() initializes the position with the given FEN string, to
+    // be played with the rules of the given variant. In crazyhouse, the
+    // pieces in hand follow the board within brackets or as a ninth rank,
+    // and a promoted piece is followed by '~'.
+    pub fn set_variant(&mut self, variant: Variant, fen_str: &str, is_chess960: bool) {
 
         self.clear();
+        self.variant = variant;
         
         let mut iter = fen_str.split_whitespace();
 
-        // 1. Piece placement
+        // 1. Piece placement, and pieces in hand
         let pieces = iter.next().unwrap();
         let mut sq = Square::A8;
+        let mut rank = 0;
+        let mut in_hand = false;
         for c in pieces.chars() {
-            if let Some(d) = c.to_digit(10) {
+            if in_hand {
+                if let Some(idx) = String::from_iter(PIECE_TO_CHAR).find(c) {
+                    if variant.has_drops() {
+                        self.in_hand[Piece(idx as u32)] += 1;
+                    }
+                }
+            } else if let Some(d) = c.to_digit(10) {
                 sq += (d as i32) * EAST; // Advance the given number of files
             } else if c == '/' {
-                sq += 2 * SOUTH;
+                rank += 1;
+                if rank == 8 {
+                    in_hand = true;
+                } else {
+                    sq += 2 * SOUTH;
+                }
+            } else if c == '[' {
+                in_hand = true;
+            } else if c == '~' {
+                if variant.has_drops() {
+                    self.promoted |= sq - EAST;
+                }
             } else if let Some(idx) = String::from_iter(PIECE_TO_CHAR).find(c) {
                 self.put_piece(Piece(idx as u32), sq);
                 sq += EAST;
@@ -35,8 +90,9 @@ impl Position {
         // standard that, in case of Chess960, if an inner rook is associated
         // with the castling right, the castling tag is replaced by the file
         // letter of the involved rook, as for the Shredder-FEN.
+        // There is no castling in antichess.
         let castling = iter.next().unwrap();
-        if castling != "-" {
+        if castling != "-" && variant.has_castling() {
             for c in castling.chars() {
                 let color = if c.is_lowercase() { BLACK } else { WHITE };
                 let rook = Piece::make(color, ROOK);
@@ -80,6 +136,17 @@ impl Position {
             }
         }
 
+        // Checks in three-check, either remaining before the halfmove clock
+        // as in "3+3", or given after the fullmove number as in "+0+0".
+        let mut fields: Vec<&str> = iter.collect();
+        if let Some(idx) = fields.iter().position(|f| f.contains('+')) {
+            let checks = fields.remove(idx);
+            if variant.counts_checks() {
+                self.set_checks(checks);
+            }
+        }
+        let mut iter = fields.into_iter();
+
         // 5-6. Halfmove clock and fullmove number
         if let Some(halfmove) = iter.next() {
             self.st_mut().rule50 = halfmove.parse().unwrap();
@@ -105,10 +172,37 @@ impl Position {
         debug_assert!(self.is_ok());
     }
 
+    // set_checks() sets the checks given by each side from the "+N+M" or
+    // "N+M" field of a three-check FEN.
+    fn set_checks(&mut self, checks: &str) {
+        let given = checks.starts_with('+');
+        let counts: Vec<i32> = checks.trim_start_matches('+')
+            .split('+')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect();
+
+        for (c, &n) in [WHITE, BLACK].iter().zip(counts.iter()) {
+            let n = if given { n } else { CHECKS_NB as i32 - n };
+            self.st_mut().checks_given[*c] = n.clamp(0, CHECKS_NB as i32) as u8;
+        }
+    }
+
     // fen() returns a FEN representation of the position. In case of Chess960
     // the Shredder-FEN notation is used.
 
     pub fn fen(&self) -> String {
+        self.fen_impl(false)
+    }
+
+    // xfen() returns a FEN representation of the position using the X-FEN
+    // notation in case of Chess960: KQkq, unless the castling rook is not
+    // the outermost one, in which case the file of the rook is given.
+
+    pub fn xfen(&self) -> String {
+        self.fen_impl(true)
+    }
+
+    fn fen_impl(&self, xfen: bool) -> String {
         let mut ss = String::new();
 
         for r in (0..8).rev() {
@@ -123,8 +217,11 @@ impl Position {
                     ss.push((48u8 + empty_cnt) as char);
                 }
                 if f < 8 {
-                    let c = self.piece_on(Square::make(File(f as u32), Rank(r as u32))).to_char();
-                    ss.push(c);
+                    let s = Square::make(File(f as u32), Rank(r as u32));
+                    ss.push(self.piece_on(s).to_char());
+                    if self.is_promoted(s) {
+                        ss.push('~');
+                    }
                     f += 1;
                 }
             }
@@ -133,12 +230,25 @@ impl Position {
             }
         }
 
+        if self.variant.has_drops() {
+            ss.push('[');
+            for c in [WHITE, BLACK] {
+                for pt in [QUEEN, ROOK, BISHOP, KNIGHT, PAWN] {
+                    let pc = Piece::make(c, pt);
+                    for _ in 0..self.in_hand[pc] {
+                        ss.push(pc.to_char());
+                    }
+                }
+            }
+            ss.push(']');
+        }
+
         ss.push_str(if self.side_to_move == WHITE { " w " } else { " b " });
 
-        self.castle_helper(&mut ss, WHITE_OO, 'K');
-        self.castle_helper(&mut ss, WHITE_OOO, 'Q');
-        self.castle_helper(&mut ss, BLACK_OO, 'k');
-        self.castle_helper(&mut ss, BLACK_OOO, 'q');
+        self.castle_helper(&mut ss, WHITE_OO, 'K', xfen);
+        self.castle_helper(&mut ss, WHITE_OOO, 'Q', xfen);
+        self.castle_helper(&mut ss, BLACK_OO, 'k', xfen);
+        self.castle_helper(&mut ss, BLACK_OOO, 'q', xfen);
 
         if !self.has_castling_right(ANY_CASTLING) {
             ss.push('-');
@@ -156,15 +266,19 @@ impl Position {
         ss.push(' ');
         ss.push_str(&(1 + self.game_ply() / 2).to_string());
 
+        if self.variant.counts_checks() {
+            ss.push_str(&format!(" +{}+{}", self.checks_given(WHITE), self.checks_given(BLACK)));
+        }
+
         ss
     }
 
-    fn castle_helper(&self, ss: &mut String, cr: CastlingRight, c: char) {
+    fn castle_helper(&self, ss: &mut String, cr: CastlingRight, c: char, xfen: bool) {
         if !self.has_castling_right(cr) {
             return;
         }
 
-        if !self.chess960 {
+        if !self.chess960 || (xfen && self.is_outermost_rook(cr)) {
             ss.push(c);
         } else {
             let f = self.castling_rook_square(cr).file();
@@ -177,5 +291,17 @@ impl Position {
         }
     }
 
+    // is_outermost_rook() tests whether the castling rook is the rook closest
+    // to the corner on its side of the king, as assumed by KQkq in X-FEN.
+    fn is_outermost_rook(&self, cr: CastlingRight) -> bool {
+        let rsq = self.castling_rook_square(cr);
+        let rook = self.piece_on(rsq);
+        let king_side = rsq.file() > self.square(rook.color(), KING).file();
+
+        VALID_FILES.into_iter()
+            .filter(|&f| if king_side { f > rsq.file() } else { f < rsq.file() })
+            .all(|f| self.piece_on(Square::make(f, rsq.rank())) != rook)
+    }
+
     
 }
\ No newline at end of file
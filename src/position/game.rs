@@ -17,7 +17,7 @@ impl Position {
         
         debug_assert!(m.is_ok());
 
-        let mut k = self.st().key ^ self.zobrist.side;
+        let mut k = self.st().key ^ ZOBRIST.side;
 
         // Copy some fields of the old state to our new StateInfo object
         // except the ones which are going to be recalculated from scratch
@@ -35,7 +35,7 @@ impl Position {
         let them = self.opposite_side();
         let from = m.from();
         let mut to = m.to();
-        let pc = self.piece_on(from);
+        let pc = self.moved_piece(m);
         let mut captured = if m.move_type() == EN_PASSANT {
             Piece::make(them, PAWN)
         } else {
@@ -47,7 +47,7 @@ impl Position {
             captured == NO_PIECE
                 || captured.color() == if m.move_type() != CASTLING { them } else { us }
         );
-        debug_assert!(captured.piece_type() != KING);
+        debug_assert!(captured.piece_type() != KING || self.variant.king_is_piece());
 
         // Castling
         if m.move_type() == CASTLING {
@@ -63,7 +63,7 @@ impl Position {
 
             self.st_mut().psq += psqt::psq(captured, rto) - psqt::psq(captured, rfrom);
 
-            k ^= self.zobrist.psq[captured][rfrom] ^ self.zobrist.psq[captured][rto];
+            k ^= ZOBRIST.psq[captured][rfrom] ^ ZOBRIST.psq[captured][rto];
             captured = NO_PIECE;
         }
 
@@ -87,6 +87,16 @@ impl Position {
                 }
             } 
 
+            // The captured piece goes to our hand, as a pawn if it was a
+            // promoted one.
+            if self.variant.has_drops() {
+                let promoted = self.is_promoted(capsq);
+                let pt = if promoted { PAWN } else { captured.piece_type() };
+                k ^= self.add_to_hand(Piece::make(us, pt));
+                self.promoted &= !capsq.bb();
+                self.st_mut().captured_promoted = promoted;
+            }
+
             // Update board and piece lists
             self.remove_piece(capsq);
 
@@ -94,8 +104,10 @@ impl Position {
                 self.board[capsq] = NO_PIECE;
             }
 
-            // Update hash
-            k ^= self.zobrist.psq[captured][capsq];
+            // Update hash keys
+            k ^= ZOBRIST.psq[captured][capsq];
+            let cnt = self.piece_count[captured];
+            self.st_mut().material_key ^= ZOBRIST.psq[captured][Square(cnt as u32)];
 
             // Update incremental scores
             self.st_mut().psq -= psqt::psq(captured, capsq);
@@ -105,11 +117,11 @@ impl Position {
         }
 
         // Update hash key
-        k ^= self.zobrist.psq[pc][from] ^ self.zobrist.psq[pc][to];
+        k ^= ZOBRIST.psq[pc][from] ^ ZOBRIST.psq[pc][to];
 
         // Reset en passant square
         if self.st_mut().ep_square != Square::NONE {
-            k ^= self.zobrist.en_passant[self.st().ep_square.file()];
+            k ^= ZOBRIST.en_passant[self.st().ep_square.file()];
             self.st_mut().ep_square = Square::NONE;
         }
 
@@ -120,36 +132,50 @@ impl Position {
         {
             let cr = self.castling_rights_mask[from]
                 | self.castling_rights_mask[to];
-            k ^= self.zobrist.castling[self.st().castling_right];
+            k ^= ZOBRIST.castling[self.st().castling_right];
             self.st_mut().castling_right &= !cr;
-            k ^= self.zobrist.castling[self.st().castling_right];
+            k ^= ZOBRIST.castling[self.st().castling_right];
         }
 
         // Move the piece. The tricky Chess960 castling is handled earlier
-        if m.move_type() != CASTLING {
+        if m.move_type() == DROP {
+            k ^= ZOBRIST.psq[pc][to] ^ self.remove_from_hand(pc);
+            self.put_piece(pc, to);
+            let cnt = self.piece_count[pc];
+            self.st_mut().material_key ^= ZOBRIST.psq[pc][Square(cnt as u32 - 1)];
+            self.st_mut().psq += psqt::psq(pc, to);
+        } else if m.move_type() != CASTLING {
             self.move_piece(from, to);
         }
 
         // If the moving piece is a pawn do some special extra work
-        if pc.piece_type() == PAWN {
+        if pc.piece_type() == PAWN && m.move_type() != DROP {
             // Set en-passant square if the moved pawn can be captured
             if to.0 ^ from.0 == 16
                 && pawn_attacks_bb(us, to - pawn_push(us)) & self.pieces_cp(them, PAWN) != 0
             {
                 self.st_mut().ep_square = to - pawn_push(us);
-                k ^= self.zobrist.en_passant[self.st().ep_square.file()];
+                k ^= ZOBRIST.en_passant[self.st().ep_square.file()];
 
             } else if m.move_type() == PROMOTION {
                 let promotion = Piece::make(us, m.promotion_type());
 
                 debug_assert!(to.relative_rank(us) == RANK_8);
-                debug_assert!(promotion.piece_type() >= KNIGHT && promotion.piece_type() <= QUEEN);
+                debug_assert!(promotion.piece_type() >= KNIGHT && promotion.piece_type() <= QUEEN
+                    || promotion.piece_type() == KING && self.variant.king_is_piece());
 
                 self.remove_piece(to);
                 self.put_piece(promotion, to);
 
+                if self.variant.has_drops() {
+                    self.promoted |= to;
+                }
+
                 // Update hash keys
-                k ^= self.zobrist.psq[pc][to] ^ self.zobrist.psq[promotion][to];
+                k ^= ZOBRIST.psq[pc][to] ^ ZOBRIST.psq[promotion][to];
+                let material = ZOBRIST.psq[promotion][Square(self.piece_count[promotion] as u32 - 1)]
+                    ^ ZOBRIST.psq[pc][Square(self.piece_count[pc] as u32)];
+                self.st_mut().material_key ^= material;
 
                 // Update incremental score
                 self.st_mut().psq +=
@@ -163,14 +189,29 @@ impl Position {
         // Update incremental scores
         self.st_mut().psq += psqt::psq(pc, to) - psqt::psq(pc, from);
 
+        // In atomic chess a capture blows up the capturing piece together
+        // with the pieces around the capture square.
+        if self.variant.has_explosions() && captured != NO_PIECE {
+            k ^= self.explode(to);
+        }
+
         // Set captured piece
         self.st_mut().captured_piece = captured;
 
+        // Count the checks given in three-check
+        if gives_check && self.variant.counts_checks() {
+            let n = self.st().checks_given[us];
+            k ^= ZOBRIST.checks[us][n as usize];
+            self.st_mut().checks_given[us] += 1;
+        }
+
         // Update the key with the final value
         self.st_mut().key = k;
 
         // Calculate checkers bitboard (if move gives check)
-        self.st_mut().checkers_bb = if gives_check {
+        self.st_mut().checkers_bb = if self.variant.has_explosions() {
+            self.atomic_checkers(them)
+        } else if gives_check {
             self.attackers_to(self.square(them, KING)) & self.pieces_c(us)
         } else {
             EMPTY_BB
@@ -222,24 +263,35 @@ impl Position {
         //let them = !us;
         let from = m.from();
         let mut to = m.to();
+
+        if self.variant.has_explosions() && self.st().captured_piece != NO_PIECE {
+            self.unexplode();
+        }
+
         let mut pc = self.piece_on(to);
 
-        debug_assert!(self.empty(from) || m.move_type() == CASTLING);
-        debug_assert!(self.st().captured_piece.piece_type() != KING);
+        debug_assert!(self.empty(from) || m.move_type() == CASTLING || m.move_type() == DROP);
+        debug_assert!(self.st().captured_piece.piece_type() != KING || self.variant.king_is_piece());
 
         if m.move_type() == PROMOTION {
 
             debug_assert!(to.relative_rank(us) == RANK_8);
             debug_assert!(pc.piece_type() == m.promotion_type());
             debug_assert!(
-                pc.piece_type() >= KNIGHT && pc.piece_type() <= QUEEN);
+                pc.piece_type() >= KNIGHT && pc.piece_type() <= QUEEN
+                || pc.piece_type() == KING && self.variant.king_is_piece());
 
             self.remove_piece(to);
+            self.promoted &= !to.bb();
             pc = Piece::make(us, PAWN);
             self.put_piece(pc, to);
         }
 
-        if  m.move_type() == CASTLING {
+        if m.move_type() == DROP {
+            self.remove_piece(to);
+            self.in_hand[pc] += 1;
+
+        } else if m.move_type() == CASTLING {
             let mut rfrom = Square::NONE;
             let mut rto = Square::NONE;
             self.do_castling::<false>(us, from, &mut to, &mut rfrom, &mut rto);
@@ -262,6 +314,15 @@ impl Position {
                     self.st().captured_piece == Piece::make(!us, PAWN));
                 }
                 self.put_piece(self.st().captured_piece, capsq);
+
+                if self.variant.has_drops() {
+                    let promoted = self.st().captured_promoted;
+                    let pt = if promoted { PAWN } else { self.st().captured_piece.piece_type() };
+                    self.in_hand[Piece::make(us, pt)] -= 1;
+                    if promoted {
+                        self.promoted |= capsq;
+                    }
+                }
             }
         }
 
@@ -284,12 +345,12 @@ impl Position {
         self.states.push(st_copy);
 
         if self.st().ep_square != Square::NONE {
-            let tmp = self.zobrist.en_passant[self.st().ep_square.file()];
+            let tmp = ZOBRIST.en_passant[self.st().ep_square.file()];
             self.st_mut().key ^= tmp;
             self.st_mut().ep_square = Square::NONE;
         }
 
-        self.st_mut().key ^= self.zobrist.side;
+        self.st_mut().key ^= ZOBRIST.side;
 
         self.st_mut().rule50 += 1;
         self.st_mut().plies_from_null = 0;
@@ -314,9 +375,15 @@ impl Position {
     /// This is meant to be helpful when debugging.
     pub fn is_ok(&self) -> bool {
 
+        // Kings blown up in atomic chess or captured in antichess are
+        // missing from the board
+        let king_ok = |c: Color| self.variant.king_can_be_lost()
+            && self.pieces_cp(c, KING) == EMPTY_BB
+            || self.piece_on(self.square(c, KING)) == Piece::make(c, KING);
+
         if self.side_to_move != WHITE && self.side_to_move != BLACK
-            || self.piece_on(self.square(WHITE, KING)) != W_KING
-            || self.piece_on(self.square(BLACK, KING)) != B_KING
+            || !king_ok(WHITE)
+            || !king_ok(BLACK)
             || self.ep_square() != Square::NONE && self.ep_square().relative_rank(self.side_to_move) != RANK_6 {
 
             panic!("pos: Default");
@@ -334,10 +401,21 @@ impl Position {
         let mut to = m.to();
 
         debug_assert!(self.moved_piece(m).color() == us);
+
+        if self.variant.has_own_legality() {
+            return self.variant_legal(m);
+        }
+
         debug_assert!(
             self.piece_on(self.square(us, KING)) == Piece::make(us, KING)
         );
 
+        // A drop cannot expose the king, and only blocking drops are
+        // generated when in check.
+        if m.move_type() == DROP {
+            return true;
+        }
+
         // En passant captures are a tricky special case. Because they are
         // uncommon, we do it simply by testing whether the king is attacked
         // after the move is made.
@@ -410,6 +488,26 @@ impl Position {
         let from = m.from();
         let to = m.to();
 
+        if !self.variant.has_checks() {
+            return false;
+        }
+
+        // A dropped piece can only give a direct check
+        if m.move_type() == DROP {
+            return self.st().check_squares[m.drop_type()] & to != 0;
+        }
+
+        // In atomic chess a capture changes the board around the capture
+        // square, and adjacent kings cannot be checked.
+        if self.variant.has_explosions() {
+            if self.capture(m) {
+                return self.atomic_capture_gives_check(m);
+            }
+            if self.kings_adjacent_after(m) {
+                return false;
+            }
+        }
+
         // Is there a direct check?
         if self.st().check_squares[self.piece_on(from).piece_type()]
             & to != 0
@@ -471,6 +569,87 @@ impl Position {
         }
     }
 
+    /// see_ge() tests whether the static exchange evaluation of a move is
+    /// greater than or equal to the given threshold. The exchange on the
+    /// destination square is played out with the least valuable attacker
+    /// first, taking x-rays into account. Special moves are assumed to pass
+    /// a threshold of zero, as are all captures of variants without
+    /// exchanges.
+    pub fn see_ge(&self, m: Move, threshold: Value) -> bool {
+
+        if m.move_type() != NORMAL || !self.variant.has_exchanges() {
+            return Value::ZERO >= threshold;
+        }
+
+        let from = m.from();
+        let to = m.to();
+
+        let mut swap = piece_value(MG, self.piece_on(to)) - threshold;
+        if swap < Value::ZERO {
+            return false;
+        }
+
+        swap = piece_value(MG, self.piece_on(from)) - swap;
+        if swap <= Value::ZERO {
+            return true;
+        }
+
+        let mut occupied = self.pieces() ^ from ^ to;
+        let mut stm = self.side_to_move();
+        let mut attackers = self.attackers_to_occ(to, occupied);
+        let mut res = 1;
+
+        loop {
+            stm = !stm;
+            attackers &= occupied;
+
+            let mut stm_attackers = attackers & self.pieces_c(stm);
+            if stm_attackers == EMPTY_BB {
+                break;
+            }
+
+            // Pinned pieces cannot take part as long as their pinners are
+            // on the board
+            if self.pinners(stm) & occupied != EMPTY_BB {
+                stm_attackers &= !self.blockers_for_king(stm);
+                if stm_attackers == EMPTY_BB {
+                    break;
+                }
+            }
+
+            res ^= 1;
+
+            // Locate the least valuable attacker. Capturing with the king
+            // is only possible if the other side has no attackers left.
+            let pt = match [PAWN, KNIGHT, BISHOP, ROOK, QUEEN].into_iter()
+                .find(|&pt| stm_attackers & self.pieces_p(pt) != EMPTY_BB)
+            {
+                Some(pt) => pt,
+                None => {
+                    let lost = attackers & !self.pieces_c(stm) != EMPTY_BB;
+                    return (res ^ lost as i32) != 0;
+                }
+            };
+
+            swap = piece_value(MG, Piece::make(stm, pt)) - swap;
+            if swap < Value(res) {
+                break;
+            }
+
+            occupied ^= lsb(stm_attackers & self.pieces_p(pt));
+
+            // Add the sliders behind the capturing piece
+            if pt == PAWN || pt == BISHOP || pt == QUEEN {
+                attackers |= attacks_bb(BISHOP, to, occupied) & self.pieces_pp(BISHOP, QUEEN);
+            }
+            if pt == ROOK || pt == QUEEN {
+                attackers |= attacks_bb(ROOK, to, occupied) & self.pieces_pp(ROOK, QUEEN);
+            }
+        }
+
+        res != 0
+    }
+
     /// Position::has_repeated() tests whether there has been at least one repetition
     /// of positions since the last capture or pawn move.
     pub fn has_repeated(&self) -> bool {
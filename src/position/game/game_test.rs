@@ -1,6 +1,7 @@
 use crate::position::Position;
 use crate::types::square::Square;
 use crate::types::r#move::Move;
+use crate::types::score::Value;
 
 
 #[test]
@@ -48,3 +49,76 @@ fn illegal_castling() {
 }
     
 
+
+#[test]
+fn draw_detection() {
+    let mut pos = Position::new();
+
+    for fen in ["8/8/3k4/8/8/3K4/8/8 w - - 0 1", "8/8/3k4/8/8/3K4/8/6B1 w - - 0 1",
+                "8/8/3k4/8/8/3K4/8/6n1 b - - 0 1", "8/2b5/3k4/8/8/3K4/8/6B1 w - - 0 1"] {
+        pos.set(fen, false);
+        assert!(pos.is_insufficient_material());
+        assert!(pos.is_draw(1));
+    }
+
+    for fen in ["8/8/3k4/8/8/3K4/8/5NB1 w - - 0 1", "8/3b4/3k4/8/8/3K4/8/6B1 w - - 0 1",
+                "8/8/3k4/8/8/3K4/8/5N1n w - - 0 1", "8/8/3k4/8/8/3K4/7P/8 w - - 0 1"] {
+        pos.set(fen, false);
+        assert!(!pos.is_insufficient_material());
+    }
+
+    // Checkmate takes precedence over the 50-move rule
+    pos.set("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80", false);
+    assert!(!pos.is_draw(1));
+    pos.set("6k1/5ppp/8/8/8/8/8/R5K1 b - - 100 80", false);
+    assert!(pos.is_draw(1));
+}
+
+#[test]
+fn repetition_before_root() {
+    let mut pos = Position::new();
+    pos.set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false);
+
+    let cycle = [(Square::G1, Square::F3), (Square::G8, Square::F6),
+                 (Square::F3, Square::G1), (Square::F6, Square::G8)];
+
+    // A single repetition is a draw only when it happened inside the search
+    for (from, to) in cycle {
+        pos.do_move(Move::make(from, to));
+    }
+    assert!(!pos.is_draw(0));
+    assert!(!pos.is_draw(4));
+    assert!(pos.is_draw(5));
+
+    // A threefold repetition is a draw even if it happened before the root
+    for (from, to) in cycle {
+        pos.do_move(Move::make(from, to));
+    }
+    assert!(pos.is_draw(0));
+}
+
+#[test]
+fn see_ge_thresholds() {
+    let mut pos = Position::new();
+
+    // The pawn on e5 is not defended
+    pos.set("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", false);
+    let m = Move::make(Square::E1, Square::E5);
+    assert!(pos.see_ge(m, Value(100)));
+    assert!(!pos.see_ge(m, Value(101)));
+
+    // N takes P, N takes N, R takes N, B takes R, Q takes B, Q takes Q:
+    // white stops after R takes N and loses a knight for a pawn.
+    pos.set("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", false);
+    let m = Move::make(Square::D3, Square::E5);
+    assert!(pos.see_ge(m, Value(-220)));
+    assert!(!pos.see_ge(m, Value(-219)));
+
+    // The knight on e2 cannot recapture while it is pinned
+    let m = Move::make(Square::C6, Square::D4);
+    pos.set("4r1k1/8/2n5/8/3P4/8/4N3/4K3 b - - 0 1", false);
+    assert!(pos.see_ge(m, Value(100)));
+    pos.set("r5k1/8/2n5/8/3P4/8/4N3/4K3 b - - 0 1", false);
+    assert!(!pos.see_ge(m, Value(1)));
+    assert!(pos.see_ge(m, Value(-220)));
+}
@@ -6,6 +6,16 @@ impl Position {
     pub fn init_states(&mut self) {
         self.states.truncate(0);
         self.states.push(StateInfo::new());
+        self.explosions.truncate(0);
+    }
+
+    // reserve_states() preallocates the StateInfo stack for the given number
+    // of moves, so that do_move() does not reallocate it during a search.
+    pub fn reserve_states(&mut self, plies: usize) {
+        self.states.reserve(plies);
+        if self.variant.has_explosions() {
+            self.explosions.reserve(plies);
+        }
     }
 
     pub fn side_to_move(&self) -> Color {
@@ -25,6 +35,9 @@ impl Position {
     }
 
     pub fn moved_piece(&self, m: Move) -> Piece {
+        if m.move_type() == DROP {
+            return Piece::make(self.side_to_move, m.drop_type());
+        }
         self.board[m.from()]
     }
 
@@ -57,10 +70,33 @@ impl Position {
         self.piece_count[Piece::make(c, pt)]
     }
 
+    pub fn count_in_hand(&self, c: Color, pt: PieceType) -> i32 {
+        self.in_hand[Piece::make(c, pt)]
+    }
+
+    pub fn checks_given(&self, c: Color) -> i32 {
+        self.st().checks_given[c] as i32
+    }
+
+    pub fn is_promoted(&self, s: Square) -> bool {
+        self.promoted & s != EMPTY_BB
+    }
+
     pub fn square(&self, c: Color, pt: PieceType) -> Square {
         lsb(self.pieces_cp(c, pt))
     }
 
+    pub fn material_key(&self) -> Key {
+        self.st().material_key
+    }
+
+    pub fn non_pawn_material(&self, c: Color) -> Value {
+        Value::KNIGHT_MG * self.count(c, KNIGHT)
+            + Value::BISHOP_MG * self.count(c, BISHOP)
+            + Value::ROOK_MG * self.count(c, ROOK)
+            + Value::QUEEN_MG * self.count(c, QUEEN)
+    }
+
     pub fn ep_square(&self) -> Square {
         self.st().ep_square
     }
@@ -158,10 +194,14 @@ impl Position {
         self.chess960
     }
 
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
     pub fn capture_or_promotion(&self, m: Move) -> bool {
         debug_assert!(m.is_ok());
         if m.move_type() != NORMAL {
-            m.move_type() != CASTLING
+            m.move_type() != CASTLING && m.move_type() != DROP
         } else {
             !self.empty(m.to())
         }
@@ -0,0 +1,215 @@
+#[cfg(test)]
+mod san_test;
+
+use super::*;
+
+impl Position {
+
+    /// move_to_san() converts a legal move to Standard Algebraic Notation,
+    /// with disambiguation, promotion and check/mate suffixes. Castling is
+    /// written as O-O / O-O-O, also in Chess960.
+    pub fn move_to_san(&mut self, m: Move) -> String {
+
+        if m == Move::NONE { return "(none)".to_string(); }
+        if m == Move::NULL { return "--".to_string(); }
+
+        let from = m.from();
+        let to = m.to();
+        let pt = self.moved_piece(m).piece_type();
+        let mut san = String::new();
+
+        if m.move_type() == DROP {
+            san.push(PIECE_TO_CHAR[pt]);
+            san.push('@');
+            san.push_str(&to.to_string());
+        } else if m.move_type() == CASTLING {
+            san.push_str(if to > from { "O-O" } else { "O-O-O" });
+        } else if pt == PAWN {
+            if self.capture(m) {
+                san.push(FILE_TO_CHAR[from.file()]);
+                san.push('x');
+            }
+            san.push_str(&to.to_string());
+            if m.move_type() == PROMOTION {
+                san.push('=');
+                san.push(PIECE_TO_CHAR[m.promotion_type()]);
+            }
+        } else {
+            san.push(PIECE_TO_CHAR[pt]);
+
+            // Disambiguate among the other pieces of the same type which can
+            // legally reach the destination square.
+            let mut ambiguous = false;
+            let mut same_file = false;
+            let mut same_rank = false;
+
+            for other in &self.legal_moves() {
+                if other == m
+                    || other.to() != to
+                    || other.move_type() == CASTLING
+                    || other.move_type() == DROP
+                    || self.moved_piece(other).piece_type() != pt {
+                    continue;
+                }
+                ambiguous = true;
+                same_file |= other.from().file() == from.file();
+                same_rank |= other.from().rank() == from.rank();
+            }
+
+            if ambiguous {
+                if !same_file {
+                    san.push(FILE_TO_CHAR[from.file()]);
+                } else if !same_rank {
+                    san.push(RANK_TO_CHAR[from.rank()]);
+                } else {
+                    san.push_str(&from.to_string());
+                }
+            }
+
+            if self.capture(m) {
+                san.push('x');
+            }
+            san.push_str(&to.to_string());
+        }
+
+        // Check and checkmate suffixes
+        if self.gives_check(m) {
+            self.do_move(m);
+            let mated = self.legal_moves().is_empty();
+            self.undo_move(m);
+            san.push(if mated { '#' } else { '+' });
+        }
+
+        san
+    }
+
+    /// parse_san() converts a move in Standard Algebraic Notation to the
+    /// corresponding legal move, or Move::NONE if there is no such move or
+    /// the notation is ambiguous. Common variants are accepted: 0-0 castling,
+    /// promotions without '=' (e8Q), missing or superfluous check suffixes,
+    /// annotations (!, ?) and long algebraic forms like Ng1-f3. Drops are
+    /// written N@f3, with or without P for pawns.
+    pub fn parse_san(&self, san: &str) -> Move {
+
+        let mut s = san.trim().trim_end_matches(['+', '#', '!', '?']).to_string();
+        if let Some(stripped) = s.strip_suffix("e.p.") {
+            s = stripped.trim_end().to_string();
+        }
+
+        let moves = self.legal_moves();
+
+        // Castling
+        let castling = s.replace('0', "O");
+        if castling == "O-O" || castling == "O-O-O" {
+            let king_side = castling == "O-O";
+            return moves.iter()
+                .find(|m| m.move_type() == CASTLING && (m.to() > m.from()) == king_side)
+                .unwrap_or(Move::NONE);
+        }
+
+        // Drops, with the piece letter omitted for pawns
+        if let Some((piece, square)) = s.split_once('@') {
+            let pt = match piece {
+                "" | "P" => PAWN,
+                "N" => KNIGHT,
+                "B" => BISHOP,
+                "R" => ROOK,
+                "Q" => QUEEN,
+                _ => return Move::NONE,
+            };
+            let mut chars = square.chars();
+            return match (chars.next(), chars.next(), chars.next()) {
+                (Some(f), Some(r), None) => parse_square(f, r)
+                    .map(|to| Move::make_drop(pt, to))
+                    .filter(|&m| moves.contains(m))
+                    .unwrap_or(Move::NONE),
+                _ => Move::NONE,
+            };
+        }
+
+        let mut chars: Vec<char> = s.chars().filter(|&c| c != 'x' && c != '-' && c != ':').collect();
+
+        // Moving piece type, pawn if no piece letter is given
+        let pt = match chars.first() {
+            Some('N') => KNIGHT,
+            Some('B') => BISHOP,
+            Some('R') => ROOK,
+            Some('Q') => QUEEN,
+            Some('K') => KING,
+            Some(_) => PAWN,
+            None => return Move::NONE,
+        };
+        if pt != PAWN {
+            chars.remove(0);
+        }
+
+        // Promotion piece, with or without '='
+        let mut promotion = NO_PIECE_TYPE;
+        if let Some(&c) = chars.last() {
+            if c.is_ascii_alphabetic() && chars.len() > 2 {
+                promotion = match c.to_ascii_uppercase() {
+                    'N' => KNIGHT,
+                    'B' => BISHOP,
+                    'R' => ROOK,
+                    'Q' => QUEEN,
+                    'K' => KING,
+                    _ => return Move::NONE,
+                };
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+
+        // Destination square
+        if chars.len() < 2 {
+            return Move::NONE;
+        }
+        let to = match parse_square(chars[chars.len() - 2], chars[chars.len() - 1]) {
+            Some(sq) => sq,
+            None => return Move::NONE,
+        };
+        chars.truncate(chars.len() - 2);
+
+        // Optional disambiguation: origin file and/or rank
+        let mut from_file = None;
+        let mut from_rank = None;
+        for c in chars {
+            match c {
+                'a'..='h' => from_file = Some(File(c as u32 - 'a' as u32)),
+                '1'..='8' => from_rank = Some(Rank(c as u32 - '1' as u32)),
+                _ => return Move::NONE,
+            }
+        }
+
+        let mut found = Move::NONE;
+        for m in &moves {
+            if m.move_type() == CASTLING
+                || m.move_type() == DROP
+                || m.to() != to
+                || self.moved_piece(m).piece_type() != pt
+                || from_file.is_some_and(|f| m.from().file() != f)
+                || from_rank.is_some_and(|r| m.from().rank() != r) {
+                continue;
+            }
+            let m_promotion = if m.move_type() == PROMOTION { m.promotion_type() } else { NO_PIECE_TYPE };
+            if m_promotion != promotion {
+                continue;
+            }
+            if found != Move::NONE {
+                return Move::NONE; // Ambiguous
+            }
+            found = m;
+        }
+
+        found
+    }
+}
+
+fn parse_square(f: char, r: char) -> Option<Square> {
+    if !('a'..='h').contains(&f) || !('1'..='8').contains(&r) {
+        return None;
+    }
+    Some(Square::make(File(f as u32 - 'a' as u32), Rank(r as u32 - '1' as u32)))
+}
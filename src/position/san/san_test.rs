@@ -0,0 +1,79 @@
+use crate::position::Position;
+use crate::types::square::Square;
+use crate::types::piece::*;
+use crate::types::r#move::*;
+
+
+#[test]
+fn move_to_san_formats_moves() {
+    let mut pos = Position::new();
+
+    pos.set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false);
+    assert_eq!(pos.move_to_san(Move::make(Square::E2, Square::E4)), "e4");
+    assert_eq!(pos.move_to_san(Move::make(Square::G1, Square::F3)), "Nf3");
+
+    // Castling, captures and check
+    pos.set("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", false);
+    assert_eq!(pos.move_to_san(Move::make_special(CASTLING, Square::E1, Square::H1)), "O-O");
+    assert_eq!(pos.move_to_san(Move::make_special(CASTLING, Square::E1, Square::A1)), "O-O-O");
+    assert_eq!(pos.move_to_san(Move::make(Square::D5, Square::E6)), "dxe6");
+    assert_eq!(pos.move_to_san(Move::make(Square::E5, Square::F7)), "Nxf7");
+    assert_eq!(pos.move_to_san(Move::make(Square::E2, Square::A6)), "Bxa6");
+
+    // Disambiguation by file, by rank and by both
+    pos.set("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", false);
+    assert_eq!(pos.move_to_san(Move::make(Square::A1, Square::D1)), "Rad1");
+    pos.set("4k3/8/R7/8/8/8/8/R3K3 w - - 0 1", false);
+    assert_eq!(pos.move_to_san(Move::make(Square::A1, Square::A3)), "R1a3");
+    pos.set("4k3/8/8/8/Q2Q4/8/8/Q3K3 w - - 0 1", false);
+    assert_eq!(pos.move_to_san(Move::make(Square::A4, Square::D1)), "Qa4d1");
+
+    // Promotion with checkmate
+    pos.set("k7/4P3/1K6/8/8/8/8/8 w - - 0 1", false);
+    assert_eq!(pos.move_to_san(Move::make_prom(Square::E7, Square::E8, QUEEN)), "e8=Q#");
+    assert_eq!(pos.move_to_san(Move::make_prom(Square::E7, Square::E8, KNIGHT)), "e8=N");
+}
+
+#[test]
+fn parse_san_accepts_common_variants() {
+    let mut pos = Position::new();
+
+    pos.set("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", false);
+    assert_eq!(pos.parse_san("O-O"), Move::make_special(CASTLING, Square::E1, Square::H1));
+    assert_eq!(pos.parse_san("0-0-0"), Move::make_special(CASTLING, Square::E1, Square::A1));
+    assert_eq!(pos.parse_san("Nxf7"), Move::make(Square::E5, Square::F7));
+    assert_eq!(pos.parse_san("Nxf7+"), Move::make(Square::E5, Square::F7));
+    assert_eq!(pos.parse_san("dxe6"), Move::make(Square::D5, Square::E6));
+    assert_eq!(pos.parse_san("Ne5-f7"), Move::make(Square::E5, Square::F7));
+    assert_eq!(pos.parse_san("Nexd7"), Move::make(Square::E5, Square::D7));
+    assert_eq!(pos.parse_san("Ke3"), Move::NONE); // Illegal
+
+    pos.set("k7/4P3/1K6/8/8/8/8/8 w - - 0 1", false);
+    assert_eq!(pos.parse_san("e8Q"), Move::make_prom(Square::E7, Square::E8, QUEEN));
+    assert_eq!(pos.parse_san("e8=Q#"), Move::make_prom(Square::E7, Square::E8, QUEEN));
+    assert_eq!(pos.parse_san("e8=n"), Move::make_prom(Square::E7, Square::E8, KNIGHT));
+    assert_eq!(pos.parse_san("e8"), Move::NONE);
+
+    pos.set("4k3/8/8/8/8/8/8/R4RK1 w - - 0 1", false);
+    assert_eq!(pos.parse_san("Rd1"), Move::NONE); // Ambiguous
+    assert_eq!(pos.parse_san("Rad1"), Move::make(Square::A1, Square::D1));
+}
+
+#[test]
+fn san_round_trips_on_all_legal_moves() {
+    let fens = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkb1r/pp1p1ppp/2p5/4P3/2B5/8/PPP1NnPP/RNBQK2R w KQkq - 0 6",
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+    ];
+    let mut pos = Position::new();
+
+    for fen in fens {
+        pos.set(fen, false);
+        for m in &pos.legal_moves() {
+            let san = pos.move_to_san(m);
+            assert_eq!(pos.parse_san(&san), m, "{} {}", fen, san);
+        }
+    }
+}
@@ -19,11 +19,13 @@ pub struct Prng {
 }
 
 impl Prng {
-    pub fn new(seed: u64) -> Prng {
+    pub const fn new(seed: u64) -> Prng {
         Prng { seed, }
     }
 
-    fn rand64(&mut self) -> u64 {
+    // rand64() is a const fn, so that tables such as the Zobrist keys can
+    // be generated at compile time.
+    pub const fn rand64(&mut self) -> u64 {
         self.seed ^= self.seed >> 12;
         self.seed ^= self.seed << 25;
         self.seed ^= self.seed >> 27;
@@ -1,42 +1,83 @@
 use crate::types::square::{SQUARE_NB};
 use crate::types::piece::{WHITE, BLACK, PIECE_NB, Color};
-use crate::types::r#move::Move;
-use crate::types::score::{Depth, Value, mated_in, mate_in, MAX_PLY, MAX_MOVES};
-use crate::movegen::ExtMove;
+use crate::types::r#move::{Move, PROMOTION};
+use crate::types::score::{Depth, Value, mated_in, mate_in, value_to_tt, value_from_tt, piece_value, EG, MAX_PLY, MAX_MOVES, MAX_DROP_MOVES};
+use crate::movegen::{ExtMove, MoveList};
 use crate::position::Position;
 use crate::evaluate::evaluate;
 use crate::movepick::MovePicker;
 use crate::tt::{TranspositionTable, TTFlag};
 use crate::uciset::{UCILimits};
 use crate::timeman::{TimeManager};
+use crate::tablebase;
 
-#[derive(Debug, Clone, Copy)]
-pub struct PV {
-    moves: [Move; MAX_MOVES],
-    count: usize
-} 
+// Captures which cannot bring the static evaluation within this margin of
+// alpha are not searched by the quiescence search
+const QS_DELTA_MARGIN: Value = Value(200);
 
-impl PV {
-    pub fn new() -> PV {
-        PV {
-            moves: [Move::NONE; MAX_MOVES],
-            count: 0,
+// PvTable is a triangular table of principal variations. The row of a ply
+// holds the best line found from the node at that ply, and is rebuilt from
+// the row of the next ply when a new best move is found, so that no PV is
+// allocated or copied by value at each node.
+#[derive(Debug, Clone)]
+pub struct PvTable {
+    moves: [[Move; MAX_PLY as usize]; MAX_PLY as usize + 1],
+    len: [usize; MAX_PLY as usize + 1],
+}
+
+impl PvTable {
+    pub fn new() -> PvTable {
+        PvTable {
+            moves: [[Move::NONE; MAX_PLY as usize]; MAX_PLY as usize + 1],
+            len: [0; MAX_PLY as usize + 1],
         }
-        
+    }
+
+    pub fn line(&self, ply: usize) -> &[Move] {
+        &self.moves[ply][..self.len[ply]]
+    }
+
+    fn set(&mut self, ply: usize, line: &[Move]) {
+        self.moves[ply][..line.len()].copy_from_slice(line);
+        self.len[ply] = line.len();
+    }
+
+    fn clear(&mut self, ply: usize) {
+        self.len[ply] = 0;
+    }
+
+    // update() sets the line at the given ply to the move followed by the
+    // line of the child node.
+    fn update(&mut self, ply: usize, m: Move) {
+        let (rows, child_rows) = self.moves.split_at_mut(ply + 1);
+        let child_len = self.len[ply + 1];
+        rows[ply][0] = m;
+        rows[ply][1..=child_len].copy_from_slice(&child_rows[0][..child_len]);
+        self.len[ply] = child_len + 1;
     }
 }
 
+impl Default for PvTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-#[derive(Debug, Clone, Copy)]
+
+// RootMoves holds the root moves in the order of the last iteration. The
+// moves of an iteration overwrite the ones of the previous iteration as
+// they are searched, and the list grows with the number of legal moves, so
+// that it does not depend on the largest move count of the variants.
+#[derive(Debug, Clone)]
 pub struct RootMoves {
-    ext_moves: [ExtMove; MAX_MOVES],
+    ext_moves: Vec<ExtMove>,
     cur: usize,
 }
 
 impl RootMoves {
     pub fn new() -> RootMoves {
         RootMoves {
-            ext_moves: [ExtMove::new(); MAX_MOVES],
+            ext_moves: Vec::new(),
             cur: 0,
         }
         
@@ -48,19 +89,23 @@ impl RootMoves {
     }
 
     pub fn next_move(&mut self) -> Move {
-        let next_move = self.ext_moves[self.cur].m;
+        let next_move = self.ext_moves.get(self.cur).map_or(Move::NONE, |ext_move| ext_move.m);
         //self.cur += 1;
         next_move
     }
 
     pub fn next(&mut self) -> ExtMove {
-        let next_move = self.ext_moves[self.cur];
+        let next_move = self.ext_moves.get(self.cur).copied().unwrap_or(ExtMove::new());
         self.cur += 1;
         next_move
     }
 
     pub fn push(&mut self, ext_move: ExtMove) {
-        self.ext_moves[self.cur] = ext_move;
+        if self.cur < self.ext_moves.len() {
+            self.ext_moves[self.cur] = ext_move;
+        } else {
+            self.ext_moves.push(ext_move);
+        }
         self.cur += 1;
     } 
 }
@@ -69,7 +114,6 @@ impl RootMoves {
 #[derive(Debug, Clone, Copy)]
 pub struct Stack {
     // ply: usize,
-    pv: [Move; MAX_PLY as usize],
     pub killers: [Move; 2],
     node_count: u32
 }
@@ -78,7 +122,6 @@ pub struct Stack {
 impl Stack {
     pub fn new() -> Stack {
         Stack {
-            pv: [Move::NONE; MAX_PLY as usize],
             killers: [Move::NONE; 2],
             node_count: 0
         }
@@ -95,6 +138,7 @@ pub const HISTORY_ZERO: History = [[Value(0); SQUARE_NB]; PIECE_NB];
 #[derive(Debug, Clone)]
 pub struct Thread {
     pub ss: [Stack; MAX_PLY as usize],
+    pv_table: PvTable,
     pub value: Value,
     root_moves: RootMoves,
     ttable: TranspositionTable,
@@ -102,6 +146,10 @@ pub struct Thread {
     limits: UCILimits,
     time: TimeManager,
     iter_time: i64,
+    nodes_searched: u64,
+    root_depth: i32,
+    stop: bool,
+    chess960: bool,
 }
 
 impl Thread {
@@ -110,6 +158,7 @@ impl Thread {
 
         let mut thread = Thread {
             ss: [Stack::new(); MAX_PLY as usize],
+            pv_table: PvTable::new(),
             value: Value(0),
             root_moves: RootMoves::new(),
             ttable: TranspositionTable::new(tt_size_mb),
@@ -117,6 +166,10 @@ impl Thread {
             limits: UCILimits::new(),
             time: TimeManager::new(),
             iter_time: 0i64,
+            nodes_searched: 0,
+            root_depth: 0,
+            stop: false,
+            chess960: false,
         };
 
         thread.init_stacks();
@@ -126,6 +179,8 @@ impl Thread {
     pub fn init(&mut self) {
         self.init_root_moves();
         self.init_stacks();
+        self.nodes_searched = 0;
+        self.stop = false;
     }
 
     pub fn init_time(&mut self, limits: UCILimits, us: Color, ply: i32) {
@@ -136,22 +191,21 @@ impl Thread {
     pub fn clear_ttable(&mut self) {
         self.ttable.clear();
     }
-    
 
-    pub fn depth(&self) -> usize {
-
-        let mut ret: usize = 0;
+    pub fn resize_ttable(&mut self, mb_size: usize) {
+        self.ttable.resize(mb_size);
+    }
 
-        for ply in 0..MAX_PLY as usize {
-            if self.ss[ply].pv[0] == Move::NONE {
-                ret = ply;
-                break;
-            }
-             
-        }
+    pub fn save_ttable(&self, path: &str) -> Result<(), String> {
+        self.ttable.save_file(path)
+    }
 
-        ret
+    // load_ttable() returns the size of the loaded table in MB
+    pub fn load_ttable(&mut self, path: &str) -> Result<usize, String> {
+        self.ttable.load_file(path)?;
+        Ok(self.ttable.size_mb())
     }
+    
 
     pub fn seldepth(&self) -> usize {
         
@@ -176,31 +230,45 @@ impl Thread {
         cnt
     }
 
-    pub fn pv(&self) -> [Move; MAX_PLY as usize] {
-        self.ss[0].pv
+    pub fn pv(&self) -> &[Move] {
+        self.pv_table.line(0)
     }
 
-    fn plies_from_mate(&self) -> i32 {
-        Value::MATE.0 - self.value.0.abs()
+    pub fn best_move_found(&self) -> Move {
+        self.pv().first().copied().unwrap_or(Move::NONE)
     }
 
-    pub fn pv_string(&self) -> String {
-        let mut ret = String::new();
-        let mut ply = 0;
-        for m in &self.pv() {
-            if *m == Move::NONE { break; }
-            if ply >= self.plies_from_mate() { break; }
-            ret = format!("{} {}", ret, m.to_string(false));
-            ply += 1;
+    // Total number of nodes visited since the search was started
+    pub fn nodes_searched(&self) -> u64 {
+        self.nodes_searched
+    }
+
+    // check_stop() raises the stop flag when the node or time limits are
+    // exceeded. The first iteration is always completed so that there is a
+    // move to play.
+    fn check_stop(&mut self) -> bool {
+        if self.stop || self.root_depth <= 1 {
+            return self.stop;
         }
-        ret
+
+        if self.limits.nodes > 0 && self.nodes_searched >= self.limits.nodes {
+            self.stop = true;
+        } else if self.nodes_searched.is_multiple_of(1024) {
+            let elapsed = self.time();
+            self.stop = (self.limits.movetime > 0 && elapsed >= self.limits.movetime)
+                || (self.limits.use_time_management() && elapsed >= self.time.maximum());
+        }
+        self.stop
     }
 
-    pub fn pv_string2(pv: &PV) -> String {
+    fn plies_from_mate(&self) -> i32 {
+        Value::MATE.0 - self.value.0.abs()
+    }
+
+    pub fn pv_string(&self) -> String {
         let mut ret = String::new();
-        for idx in 0..pv.count {
-            let m = pv.moves[idx];
-            ret = format!("{} {}", ret, m.to_string(false));
+        for m in self.pv() {
+            ret = format!("{} {}", ret, m.to_string(self.chess960));
         }
         ret
     }
@@ -252,17 +320,9 @@ impl Thread {
         
     }
 
-    pub fn info2(&self, depth: i32, pv: &PV) -> String {
-
-        format!("depth {} seldepth {} time {} nodes {} score {} nps {} pv{}", 
-            depth, self.seldepth(), self.time(), self.nodes(), self.score(),
-            self.nps(), Self::pv_string2(pv))
-        
-    }
-
     pub fn best_move(&self) -> String {
-        let best_move_str = self.pv()[0].to_string(false);
-        let ponder_str = self.pv()[1].to_string(false);
+        let best_move_str = self.best_move_found().to_string(self.chess960);
+        let ponder_str = self.pv().get(1).copied().unwrap_or(Move::NONE).to_string(self.chess960);
         format!("bestmove {} ponder {}", best_move_str, ponder_str)
 
     }
@@ -271,10 +331,6 @@ impl Thread {
         println!("info {}", self.info(depth));
     }
 
-    pub fn print_info2(&self, depth: i32, pv: &PV) {
-        println!("info {}", self.info2(depth, pv));
-    }
-
     pub fn print_best_move(&self) {
         println!("{}", self.best_move());
     }
@@ -284,6 +340,7 @@ impl Thread {
         for ply in 0..MAX_PLY as usize {
             self.ss[ply] = Stack::new();
         }
+        self.pv_table.clear(0);
 
     }
 
@@ -301,7 +358,7 @@ impl Thread {
         loop {
             let ext_move = self.root_moves.next();
             if ext_move.m == Move::NONE { break };
-            println!("{} {:?}", ext_move.m.to_string(false), ext_move.value);
+            println!("{} {:?}", ext_move.m.to_string(self.chess960), ext_move.value);
         } 
     }
 
@@ -325,25 +382,53 @@ impl Thread {
         let mut ebf;
         let mut nps;
 
-        let max_depth = if self.limits.infinite || self.limits.use_time_management() {
+        let max_depth = if self.limits.depth == 0 || self.limits.use_time_management() {
             MAX_PLY
         } else {
             self.limits.depth as i32
         };
         
         let mut curr_depth = 1;
+        self.chess960 = pos.is_chess960();
+        pos.reserve_states(MAX_PLY as usize);
+        self.ttable.new_search();
+
+        // At the root, play the tablebase move without searching
+        if let Some((m, v)) = tablebase::probe_root(pos) {
+            self.init_stacks();
+            self.pv_table.set(0, &[m]);
+            self.value = tablebase::value(v, 0);
+            println!("info depth 1 score {} nodes 0 pv {}", self.score(), m.to_string(self.chess960));
+            self.print_best_move();
+            return;
+        }
+
         //println!("{} {}", self.time.optimum(), self.limits.use_time_management());
-        while (curr_depth <= max_depth && !self.limits.use_time_management()) || 
-        (self.limits.use_time_management() && (next_time < self.time.optimum() || curr_depth <=1)) {
+        while ((curr_depth <= max_depth && !self.limits.use_time_management()) || 
+        (self.limits.use_time_management() && (next_time < self.time.optimum() || curr_depth <=1)))
+        && !self.stop {
 
-            let mut pv = PV::new();
+            let prev_pv = self.pv().to_vec();
+            let prev_value = self.value;
             
             self.clear_history();
             self.init_stacks();
+            self.root_depth = curr_depth;
+
+            // The move lists get the larger size only in variants with drops
+            self.value = if pos.variant().has_drops() {
+                search::<MAX_DROP_MOVES>(pos, ply, alpha, beta, Depth(curr_depth), self)
+            } else {
+                search::<MAX_MOVES>(pos, ply, alpha, beta, Depth(curr_depth), self)
+            };
+
+            // An interrupted iteration is discarded, keeping the previous PV
+            if self.stop {
+                self.pv_table.set(0, &prev_pv);
+                self.value = prev_value;
+                break;
+            }
 
-            self.value = search(pos, ply, alpha, beta, Depth(curr_depth), &mut pv, self);
-
-            
 
             self.root_moves.sort();
 
@@ -370,8 +455,7 @@ impl Thread {
             
             prev_time = elapsed;
 
-            //self.print_info(curr_depth);
-            self.print_info2(curr_depth, &pv);
+            self.print_info(curr_depth);
             //println!("ebf {} next_time {} iter time {}\n", ebf, next_time, self.iter_time);
 
             curr_depth += 1;
@@ -396,24 +480,6 @@ impl Thread {
     
 // }
 
-fn update_pv(ss: &mut [Stack], ply: usize, m: Move){
-    ss[ply].pv[0] = m;
-    let mut idx = 0;
-    while ss[ply+1].pv[idx] != Move::NONE {
-        ss[ply].pv[idx+1] = ss[ply+1].pv[idx];
-        idx += 1;
-    }
-    
-}
-
-fn update_pv2(pv: &mut PV, m: Move, child_pv: &PV){
-    pv.moves[0] = m;
-    for idx in 0..child_pv.count {
-        pv.moves[idx+1] = child_pv.moves[idx];
-    }
-    pv.count = child_pv.count + 1;   
-}
-
 fn update_killers(ss: &mut [Stack], ply: usize, m: Move) {
     if m != ss[ply].killers[0]{
         ss[ply].killers[1] = ss[ply].killers[0];
@@ -422,12 +488,15 @@ fn update_killers(ss: &mut [Stack], ply: usize, m: Move) {
 }
 
 
-fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut depth: Depth, pv: &mut PV, thread: &mut Thread) -> Value {
+fn search<const N: usize>(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut depth: Depth, thread: &mut Thread) -> Value {
 
+    thread.pv_table.clear(ply);
     thread.ss[ply].node_count += 1;
+    thread.nodes_searched += 1;
 
-    let mut child_pv = PV::new();
-    pv.count = 0;
+    if thread.check_stop() {
+        return Value::ZERO;
+    }
 
     let mut num_legal = 0;
     let mut num_played = 0;
@@ -438,6 +507,7 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
     let mut value;
 
     let (tt_hit, tt_value, tt_flag, tt_depth, tt_move) = thread.ttable.probe(pos.key());
+    let tt_value = value_from_tt(tt_value, ply as i32);
 
     // If tt_hit return the move immediately. NEVER DO THIS ON PV NODE!.
     if tt_hit && tt_move != Move::NONE && tt_depth >= depth && !pv_node {
@@ -455,9 +525,30 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
         }
     }
 
-    // Checks for 50 rule count and repetition draw. Stalemate is handled later.
-    if pos.is_draw(ply as i32) {
-        return Value::DRAW;
+    if !root_node {
+        // The game may be over by the rules of the variant
+        if let Some(v) = pos.variant_end(ply as i32) {
+            return v;
+        }
+
+        // Checks for 50 rule count, repetition and insufficient material
+        // draws. Stalemate is handled later.
+        if pos.is_draw(ply as i32) {
+            return Value::DRAW;
+        }
+
+        // Early draw cutoff if a repetition is within reach
+        if alpha < Value::DRAW && pos.upcoming_repetition(ply as i32) {
+            alpha = Value::DRAW;
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        // Tablebase positions are scored by their distance to mate
+        if let Some(v) = tablebase::probe(pos) {
+            return tablebase::value(v, ply as i32);
+        }
     }
 
     // For depth <=0 go in quiescent search
@@ -466,15 +557,16 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
             depth = Depth(1);
         } else {
             thread.ss[ply].node_count -= 1;
-            return qsearch(pos, ply, alpha, beta, depth, &mut child_pv, thread);
+            return qsearch::<N>(pos, ply, alpha, beta, Depth::QS_CHECKS, thread);
         }
        
     }
 
-    // Null move pruning
-    if pos.checkers() == 0 {
+    // Null move pruning. Not in antichess, where captures are compulsory
+    // and zugzwang is the rule.
+    if pos.checkers() == 0 && pos.variant().allows_null_move() {
         pos.do_null_move();
-        value = -search(pos, ply+2, -beta, -beta + 1, depth - 3, &mut child_pv, thread);
+        value = -search::<N>(pos, ply+2, -beta, -beta + 1, depth - 3, thread);
         pos.undo_null_move();
         if value >= beta {
             return beta;
@@ -483,7 +575,7 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
     
     // Init movepicker
 
-    let mut mp = MovePicker::new(pos, tt_move, ply, depth, &mut thread.ss, thread.history);
+    let mut mp = MovePicker::<N>::new(pos, tt_move, ply, depth, &thread.ss);
 
     loop {
 
@@ -495,7 +587,7 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
             tmp
         } else { 
             // At a non root_node, het the next_move from the movepicker.
-            mp.next_move(pos, false)
+            mp.next_move(pos, &thread.history, false)
         };
 
         if m == Move::NONE { break; }
@@ -505,29 +597,34 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
         // TODO: Add some pruning here
 
         pos.do_move(m);
+        thread.ttable.prefetch(pos.key());
 
         num_played += 1;
 
         // PVS, first node of PV line with full window, other nodes with null-window.
         if pv_node && num_played == 1 {
-            value =  -search(pos, ply+1, -beta, -alpha, depth-1, &mut child_pv, thread);
+            value =  -search::<N>(pos, ply+1, -beta, -alpha, depth-1, thread);
         } else {
             red = 2;
-            value =  -search(pos, ply+1, -alpha-1, -alpha, depth-1-red, &mut child_pv, thread);
+            value =  -search::<N>(pos, ply+1, -alpha-1, -alpha, depth-1-red, thread);
             if value > alpha && red > 0 {
-                value =  -search(pos, ply+1, -alpha-1, -alpha, depth-1, &mut child_pv, thread);
+                value =  -search::<N>(pos, ply+1, -alpha-1, -alpha, depth-1, thread);
             }
             if value > alpha && (root_node || value < beta) {
-                value =  -search(pos, ply+1, -beta, -alpha, depth-1, &mut child_pv, thread);
+                value =  -search::<N>(pos, ply+1, -beta, -alpha, depth-1, thread);
             }
 
         }
         
         pos.undo_move(m);
 
+        if thread.stop {
+            return Value::ZERO;
+        }
+
         if value >= beta { // Fail high.
             // Update TT
-            thread.ttable.save(pos.key(), beta, TTFlag::LOWER, depth, m);
+            thread.ttable.save(pos.key(), value_to_tt(beta, ply as i32), TTFlag::LOWER, depth, m);
 
             if !pos.capture(m) {
 
@@ -536,7 +633,7 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
                 update_killers(&mut thread.ss, ply, m);
 
                 // Update history
-                thread.history[pos.piece_on(m.from())][m.to()] += Value(depth.0 * depth.0);
+                thread.history[pos.moved_piece(m)][m.to()] += Value(depth.0 * depth.0);
             }
 
             return beta;
@@ -544,11 +641,10 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
 
         if value > alpha { // New PV move
             alpha = value;
-            thread.ttable.save(pos.key(), value, TTFlag::EXACT, depth, m);
-            update_pv(&mut thread.ss, ply, m);
-            update_pv2(pv, m, &child_pv);
+            thread.ttable.save(pos.key(), value_to_tt(value, ply as i32), TTFlag::EXACT, depth, m);
+            thread.pv_table.update(ply, m);
         } else { // fail low
-            thread.ttable.save(pos.key(), alpha, TTFlag::UPPER, depth, Move::NONE);
+            thread.ttable.save(pos.key(), value_to_tt(alpha, ply as i32), TTFlag::UPPER, depth, Move::NONE);
         }
 
         if root_node { 
@@ -560,6 +656,11 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
 
     // If there are no legal moves at this point, it is either checkmate or stalemate
     if num_legal == 0 {
+        // In antichess the side without moves wins
+        if pos.variant().stalemate_wins() {
+            return mate_in(ply as i32);
+        }
+
         // Stalemate
         if pos.checkers() == 0 {
             return Value::DRAW;
@@ -572,72 +673,233 @@ fn search(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, mut dep
 
 }
 
-fn qsearch(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, depth: Depth, pv: &mut PV, thread: &mut Thread) -> Value {
-
-    let mut child_pv = PV::new();
-    pv.count = 0;
+// qsearch() is the quiescence search, called by search() at depth 0 with
+// depth decreasing by one at each ply. When in check all the evasions are
+// searched and there is no standing pat. Otherwise the captures are
+// searched, and the quiet checks at the first ply, skipping the moves which
+// lose material by SEE and the captures which cannot raise the static
+// evaluation to alpha (delta pruning).
+fn qsearch<const N: usize>(pos: &mut Position, ply: usize, mut alpha: Value, beta: Value, depth: Depth, thread: &mut Thread) -> Value {
 
     thread.ss[ply].node_count += 1;
+    thread.nodes_searched += 1;
+
+    if thread.check_stop() {
+        return Value::ZERO;
+    }
+
+    if let Some(v) = pos.variant_end(ply as i32) {
+        return v;
+    }
 
-    // Checks for 50 rule count and repetition draw. Stalemate is handled later.
+    // Checks for 50 rule count, repetition and insufficient material draws.
+    // Stalemate is handled later.
     if pos.is_draw(ply as i32) {
         return Value::DRAW;
     }
 
-    let mut value = evaluate(pos);
-    if value >= beta {
-        return beta;
+    // Early draw cutoff if a repetition is within reach
+    if alpha < Value::DRAW && pos.upcoming_repetition(ply as i32) {
+        alpha = Value::DRAW;
+        if alpha >= beta {
+            return alpha;
+        }
     }
-    if value > alpha {
-        alpha = value;
+
+    let pv_node = beta - alpha > Value(1);
+    let in_check = pos.checkers() != 0;
+    let old_alpha = alpha;
+
+    // The entries of the first ply, where the quiet checks are searched, are
+    // deeper than the ones of the next plies
+    let tt_depth = if in_check || depth >= Depth::QS_CHECKS {
+        Depth::QS_CHECKS
+    } else {
+        Depth::QS_NO_CHECKS
+    };
+
+    let (tt_hit, tt_value, tt_flag, tt_entry_depth, tt_move) = thread.ttable.probe(pos.key());
+    let tt_value = value_from_tt(tt_value, ply as i32);
+
+    if tt_hit && tt_entry_depth >= tt_depth && !pv_node {
+        if tt_flag == TTFlag::LOWER && tt_value >= beta {
+            return beta;
+        }
+        if tt_flag == TTFlag::EXACT {
+            return tt_value;
+        }
+        if tt_flag == TTFlag::UPPER && tt_value <= alpha {
+            return alpha;
+        }
     }
 
-    let mut mp = MovePicker::new(pos, Move::NONE, ply, depth, &mut thread.ss, thread.history);
-    let mut num_moves = 0;
+    // Captures are compulsory in antichess, so that there is no standing
+    // pat when a capture is possible, and the side without moves wins. The
+    // legal moves start with the captures.
+    let mut must_capture = false;
+    if pos.variant().compulsory_captures() {
+        let moves = MoveList::<N>::legal(pos);
+        if moves.is_empty() {
+            return mate_in(ply as i32);
+        }
+        must_capture = pos.capture(moves[0]);
+    }
 
-    // let mut list = [ExtMove {m: Move::NONE, value: 0}; 200];
-    
-    // if pos.checkers() == 0 {
-    //     num_moves = generate(CAPTURES, &pos, &mut list, num_moves);
-    //     num_moves = generate(QUIET_CHECKS, &pos, &mut list, num_moves);
+    // Stand pat, unless in check or forced to capture
+    let mut futility_base = -Value::INFINITE;
+    if !in_check && !must_capture {
+        let stand_pat = evaluate(pos);
+        if stand_pat >= beta {
+            if !tt_hit {
+                thread.ttable.save(pos.key(), value_to_tt(beta, ply as i32), TTFlag::LOWER, tt_depth, Move::NONE);
+            }
+            return beta;
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+        futility_base = stand_pat + QS_DELTA_MARGIN;
+    }
 
-    // } else {
-    //     num_moves = generate(EVASIONS, &pos, &mut list, num_moves);
-    // }
-    
+    let mut mp = MovePicker::<N>::new(pos, tt_move, ply, depth, &thread.ss);
+    let mut best_move = Move::NONE;
+    let mut num_moves = 0;
 
     loop {
-        let m = mp.next_move(pos, true);
+        let m = mp.next_move(pos, &thread.history, true);
         if m == Move::NONE { break; }
         if !pos.legal(m) { continue; }
         num_moves += 1;
 
+        // Nothing is pruned when in check, where every evasion is needed
+        // to tell a mate
+        if !in_check && pos.variant().has_exchanges() {
+            if !pos.gives_check(m) && m.move_type() != PROMOTION
+                && futility_base + piece_value(EG, pos.piece_on(m.to())) <= alpha
+            {
+                continue;
+            }
+
+            if !pos.see_ge(m, Value::ZERO) {
+                continue;
+            }
+        }
+
         pos.do_move(m);
+        thread.ttable.prefetch(pos.key());
 
-        value = -qsearch(pos, ply+1, -beta, -alpha, depth-1, &mut child_pv, thread);
+        let value = -qsearch::<N>(pos, ply+1, -beta, -alpha, depth-1, thread);
 
         pos.undo_move(m);
 
+        if thread.stop {
+            return Value::ZERO;
+        }
+
         if value >= beta {
             update_killers(&mut thread.ss, ply, m);
+            thread.ttable.save(pos.key(), value_to_tt(beta, ply as i32), TTFlag::LOWER, tt_depth, m);
             return beta;
         }
         if value > alpha {
             alpha = value;
-            update_pv2(pv, m, &child_pv);
+            best_move = m;
         }
 
     }
 
-    // If there are no moves at this point ans we are in check, it is checkmate, 
-    // since all evasions have been generated
-    // If there are no moves and we are not in check, it is not necessarily stalemate,
-    // since not all moves are generated in qsearch
-    if num_moves == 0 && pos.checkers() != 0 {
+    // If there are no moves at this point and we are in check, it is
+    // checkmate, since all evasions have been generated. If there are no
+    // moves and we are not in check, it is not necessarily stalemate, since
+    // not all moves are generated in qsearch.
+    if num_moves == 0 && in_check {
         return mated_in(ply as i32);
-    } 
+    }
+
+    let flag = if alpha > old_alpha { TTFlag::EXACT } else { TTFlag::UPPER };
+    thread.ttable.save(pos.key(), value_to_tt(alpha, ply as i32), flag, tt_depth, best_move);
 
     alpha
 
 }
 
+#[cfg(test)]
+mod search_test {
+
+    use super::*;
+    use crate::types::square::Square;
+    use crate::variant::Variant;
+
+    #[test]
+    fn pv_table() {
+        let e2e4 = Move::make(Square::E2, Square::E4);
+        let e7e5 = Move::make(Square::E7, Square::E5);
+        let g8f6 = Move::make(Square::G8, Square::F6);
+        let d2d4 = Move::make(Square::D2, Square::D4);
+        let mut pv = PvTable::new();
+
+        pv.clear(2);
+        pv.update(1, e7e5);
+        pv.update(0, e2e4);
+        assert_eq!(pv.line(0), &[e2e4, e7e5]);
+
+        // A new best move at ply 1 does not change the line at ply 0 until
+        // it is propagated up.
+        pv.clear(2);
+        pv.update(1, g8f6);
+        assert_eq!(pv.line(0), &[e2e4, e7e5]);
+        pv.update(0, e2e4);
+        assert_eq!(pv.line(0), &[e2e4, g8f6]);
+
+        pv.clear(1);
+        pv.update(0, d2d4);
+        assert_eq!(pv.line(0), &[d2d4]);
+    }
+
+    #[test]
+    fn qsearch_does_not_stand_pat_in_check() {
+        let mut thread = Thread::new(1);
+        let mut pos = Position::new();
+
+        // White is a queen up but mated on the back rank
+        pos.set("6k1/5ppp/8/8/8/8/Q4PPP/4r1K1 w - - 0 1", false);
+        let value = qsearch::<MAX_MOVES>(&mut pos, 0, Value(-1), Value::ZERO, Depth::QS_CHECKS, &mut thread);
+        assert_eq!(value, mated_in(0));
+
+        // Black mates with a quiet check at the first ply only
+        pos.set("4r1k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", false);
+        let value = qsearch::<MAX_MOVES>(&mut pos, 0, -Value::INFINITE, Value::INFINITE, Depth::QS_CHECKS, &mut thread);
+        assert_eq!(value, mate_in(1));
+        thread.clear_ttable();
+        let value = qsearch::<MAX_MOVES>(&mut pos, 0, -Value::INFINITE, Value::INFINITE, Depth::QS_NO_CHECKS, &mut thread);
+        assert!(value < Value::KNOWN_WIN);
+    }
+
+    #[test]
+    fn qsearch_antichess() {
+        let mut thread = Thread::new(1);
+        let mut pos = Position::new();
+
+        // White has no moves and wins
+        pos.set_variant(Variant::Antichess, "7k/8/8/8/8/p7/P7/8 w - - 0 1", false);
+        let value = qsearch::<MAX_MOVES>(&mut pos, 0, -Value::INFINITE, Value::INFINITE, Depth::QS_CHECKS, &mut thread);
+        assert_eq!(value, mate_in(0));
+
+        // White stands pat one piece down, but must capture the knight and
+        // get even
+        pos.set_variant(Variant::Antichess, "7k/8/8/8/8/8/8/Rn6 w - - 0 1", false);
+        assert_eq!(evaluate(&pos), Value::PAWN_MG);
+        let value = qsearch::<MAX_MOVES>(&mut pos, 0, -Value::INFINITE, Value::INFINITE, Depth::QS_CHECKS, &mut thread);
+        assert_eq!(value, Value::ZERO);
+    }
+
+    #[test]
+    fn mate_scores_are_node_relative_in_tt() {
+        // A mate in 5 plies from the root is a mate in 2 from ply 3
+        assert_eq!(value_to_tt(mate_in(5), 3), mate_in(2));
+        assert_eq!(value_to_tt(mated_in(5), 3), mated_in(2));
+        assert_eq!(value_from_tt(mate_in(2), 7), mate_in(9));
+        assert_eq!(value_from_tt(mated_in(2), 7), mated_in(9));
+        assert_eq!(value_to_tt(Value(150), 3), Value(150));
+    }
+}
@@ -0,0 +1,860 @@
+use crate::attacks::attack_bb::*;
+use crate::position::Position;
+use crate::types::bitboard::*;
+use crate::types::piece::*;
+use crate::types::r#move::*;
+use crate::types::score::*;
+use crate::types::square::*;
+use crate::zobrist::{Key, KEY_ZERO, ZOBRIST};
+
+use std::fs;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Tablebase values are distances to mate in plies, from the point of view
+// of the side to move: n > 0 wins in n plies, -(n + 1) loses in n plies (so
+// -1 is checkmated) and 0 is a draw. Castling and en passant are not
+// encoded, positions with castling rights or an en passant square are not
+// probed, and there are no tables with pawns on both sides, where a double
+// push could be captured en passant.
+pub const TB_DRAW: i16 = 0;
+const UNKNOWN: i16 = i16::MIN;
+const INVALID: i16 = i16::MIN + 1;
+
+pub const MAX_TB_PIECES: usize = 4;
+
+// Tables generated by the "tbgen" command when no material is given
+pub const DEFAULT_TABLES: [&str; 6] = ["KPvK", "KRvK", "KQvK", "KBNvK", "KRvKP", "KQvKR"];
+
+const TB_MAGIC: &[u8; 8] = b"SNOWTB1\0";
+
+const PIECE_ORDER: [PieceType; 5] = [QUEEN, ROOK, BISHOP, KNIGHT, PAWN];
+
+// after_move() converts the value of a position to the value of its parent
+pub fn after_move(v: i16) -> i16 {
+    if v > 0 { -(v + 2) } else if v < 0 { -v } else { TB_DRAW }
+}
+
+// rank() orders the values from the side to move point of view: shortest
+// wins first, longest losses last.
+fn rank(v: i16) -> i32 {
+    if v > 0 { 10000 - v as i32 } else if v < 0 { -10000 - v as i32 - 1 } else { 0 }
+}
+
+fn better(a: i16, b: i16) -> i16 {
+    if b == UNKNOWN || (a != UNKNOWN && rank(a) >= rank(b)) { a } else { b }
+}
+
+// value() converts a tablebase value to a search value at the given ply
+pub fn value(v: i16, ply: i32) -> Value {
+    if v > 0 {
+        mate_in(ply + v as i32)
+    } else if v < 0 {
+        mated_in(ply - v as i32 - 1)
+    } else {
+        Value::DRAW
+    }
+}
+
+fn piece_value(pt: PieceType) -> u32 {
+    match pt {
+        QUEEN => 9,
+        ROOK => 5,
+        BISHOP | KNIGHT => 3,
+        PAWN => 1,
+        _ => 0,
+    }
+}
+
+fn char_to_piece_type(c: char) -> Option<PieceType> {
+    match c.to_ascii_uppercase() {
+        'K' => Some(KING),
+        'Q' => Some(QUEEN),
+        'R' => Some(ROOK),
+        'B' => Some(BISHOP),
+        'N' => Some(KNIGHT),
+        'P' => Some(PAWN),
+        _ => None,
+    }
+}
+
+// material_name() returns the name of a material signature, e.g. "KRvKP",
+// pieces listed from the strongest.
+fn material_name(pieces: &[Piece]) -> String {
+    let side = |c: Color| {
+        let mut s = String::from("K");
+        for pt in PIECE_ORDER {
+            let count = pieces.iter().filter(|&&pc| pc == Piece::make(c, pt)).count();
+            s.push_str(&PIECE_TO_CHAR[pt].to_string().repeat(count));
+        }
+        s
+    };
+    format!("{}v{}", side(WHITE), side(BLACK))
+}
+
+// parse_name() reads a material signature given as "KRvKP" or "KRKP"
+fn parse_name(name: &str) -> Option<Vec<Piece>> {
+    let name = name.replace('v', "");
+    let second_king = name.char_indices().skip(1).find(|&(_, c)| c == 'K')?.0;
+    let mut pieces = Vec::new();
+
+    for (idx, c) in name.char_indices() {
+        let color = if idx < second_king { WHITE } else { BLACK };
+        pieces.push(Piece::make(color, char_to_piece_type(c)?));
+    }
+
+    let kings = pieces.iter().filter(|pc| pc.piece_type() == KING).count();
+    if kings != 2 || pieces.len() > MAX_TB_PIECES || pieces[0] != W_KING {
+        return None;
+    }
+    Some(pieces)
+}
+
+// material_key() computes the material key of the pieces, as the one of
+// Position, with colors swapped or not.
+fn material_key(pieces: &[Piece], swapped: bool) -> Key {
+    let mut counts = [0u32; PIECE_NB];
+    let mut key = KEY_ZERO;
+
+    for &pc in pieces {
+        let pc = if swapped { !pc } else { pc };
+        key ^= ZOBRIST.psq[pc][Square(counts[pc.0 as usize])];
+        counts[pc.0 as usize] += 1;
+    }
+    key
+}
+
+// canonical_name() returns the name under which a signature is stored, the
+// stronger side being white, and whether colors must be swapped to get it.
+fn canonical_name(pieces: &[Piece]) -> (String, bool) {
+    let strength = |c: Color| pieces.iter()
+        .filter(|pc| pc.color() == c)
+        .map(|pc| piece_value(pc.piece_type()))
+        .sum::<u32>();
+
+    let name = material_name(pieces);
+    let swapped: Vec<Piece> = pieces.iter().map(|&pc| !pc).collect();
+    let swapped_name = material_name(&swapped);
+
+    if (strength(BLACK), &swapped_name) > (strength(WHITE), &name) {
+        (swapped_name, true)
+    } else {
+        (name, false)
+    }
+}
+
+// Board is the small board used to generate the tables: pieces and their
+// squares, in the order of the table pieces.
+#[derive(Clone, Copy)]
+struct Board {
+    n: usize,
+    pieces: [Piece; MAX_TB_PIECES],
+    squares: [Square; MAX_TB_PIECES],
+}
+
+impl Board {
+
+    // new() returns None if pieces overlap or a pawn is on a back rank
+    fn new(pieces: &[Piece], squares: &[Square]) -> Option<Board> {
+        let mut board = Board { n: pieces.len(), pieces: [NO_PIECE; MAX_TB_PIECES], squares: [Square::NONE; MAX_TB_PIECES] };
+        let mut occ = 0u64;
+
+        for i in 0..pieces.len() {
+            let s = squares[i];
+            if occ & (1 << s.0) != 0
+                || (pieces[i].piece_type() == PAWN && (s.rank() == RANK_1 || s.rank() == RANK_8)) {
+                return None;
+            }
+            occ |= 1 << s.0;
+            board.pieces[i] = pieces[i];
+            board.squares[i] = s;
+        }
+        Some(board)
+    }
+
+    fn occupied(&self) -> Bitboard {
+        let mut occ = EMPTY_BB;
+        for i in 0..self.n {
+            occ |= square_bb(self.squares[i]);
+        }
+        occ
+    }
+
+    fn occupied_by(&self, c: Color) -> Bitboard {
+        let mut occ = EMPTY_BB;
+        for i in 0..self.n {
+            if self.pieces[i].color() == c {
+                occ |= square_bb(self.squares[i]);
+            }
+        }
+        occ
+    }
+
+    fn attacks(&self, i: usize, occ: Bitboard) -> Bitboard {
+        let pc = self.pieces[i];
+        match pc.piece_type() {
+            PAWN => pawn_attacks_bb(pc.color(), self.squares[i]),
+            pt => attacks_bb(pt, self.squares[i], occ),
+        }
+    }
+
+    fn in_check(&self, c: Color) -> bool {
+        let occ = self.occupied();
+        let ksq = (0..self.n).find(|&i| self.pieces[i] == Piece::make(c, KING)).map(|i| self.squares[i]).unwrap();
+
+        (0..self.n).any(|i| self.pieces[i].color() != c && self.attacks(i, occ) & square_bb(ksq) != 0)
+    }
+
+    fn with_move(&self, i: usize, to: Square, promotion: PieceType) -> Board {
+        let mut child = *self;
+
+        if let Some(j) = (0..self.n).find(|&j| self.squares[j] == to) {
+            for k in j..self.n - 1 {
+                child.pieces[k] = child.pieces[k + 1];
+                child.squares[k] = child.squares[k + 1];
+            }
+            child.n -= 1;
+        }
+
+        let i = if (0..self.n).any(|j| j < i && self.squares[j] == to) { i - 1 } else { i };
+        child.squares[i] = to;
+        if promotion != NO_PIECE_TYPE {
+            child.pieces[i] = Piece::make(self.pieces[i].color(), promotion);
+        }
+        child
+    }
+
+    // for_each_move() calls 'f' with each position reached by a legal move
+    // of 'us', and whether the move is quiet (neither a capture nor a
+    // promotion), so that the position is in the same table.
+    fn for_each_move<F: FnMut(&Board, bool)>(&self, us: Color, mut f: F) {
+        let occ = self.occupied();
+        let own = self.occupied_by(us);
+        let them = self.occupied_by(!us);
+
+        for i in 0..self.n {
+            let pc = self.pieces[i];
+            if pc.color() != us {
+                continue;
+            }
+            let from = self.squares[i];
+            let mut targets = EMPTY_BB;
+
+            if pc.piece_type() == PAWN {
+                let push = from + pawn_push(us);
+                if occ & square_bb(push) == 0 {
+                    targets |= square_bb(push);
+                    if from.relative_rank(us) == RANK_2 && occ & square_bb(push + pawn_push(us)) == 0 {
+                        targets |= square_bb(push + pawn_push(us));
+                    }
+                }
+                targets |= pawn_attacks_bb(us, from) & them;
+            } else {
+                targets = self.attacks(i, occ) & !own;
+            }
+
+            for to in targets {
+                let capture = them & square_bb(to) != 0;
+                let promotions: &[PieceType] = if pc.piece_type() == PAWN && to.relative_rank(us) == RANK_8 {
+                    &[QUEEN, ROOK, BISHOP, KNIGHT]
+                } else {
+                    &[NO_PIECE_TYPE]
+                };
+
+                for &promotion in promotions {
+                    let child = self.with_move(i, to, promotion);
+                    if !child.in_check(us) {
+                        f(&child, !capture && promotion == NO_PIECE_TYPE);
+                    }
+                }
+            }
+        }
+    }
+
+    // for_each_unmove() calls 'f' with each position from which a quiet
+    // move of 'them' leads to this position. Legality of the resulting
+    // position is left to the caller.
+    fn for_each_unmove<F: FnMut(&Board)>(&self, them: Color, mut f: F) {
+        let occ = self.occupied();
+
+        for i in 0..self.n {
+            let pc = self.pieces[i];
+            if pc.color() != them {
+                continue;
+            }
+            let to = self.squares[i];
+
+            if pc.piece_type() == PAWN {
+                let from = to - pawn_push(them);
+                if occ & square_bb(from) != 0 || from.relative_rank(them) == RANK_1 {
+                    continue;
+                }
+                f(&self.with_move(i, from, NO_PIECE_TYPE));
+
+                let from2 = from - pawn_push(them);
+                if to.relative_rank(them) == RANK_4 && occ & square_bb(from2) == 0 {
+                    f(&self.with_move(i, from2, NO_PIECE_TYPE));
+                }
+            } else {
+                for from in self.attacks(i, occ) & !occ {
+                    f(&self.with_move(i, from, NO_PIECE_TYPE));
+                }
+            }
+        }
+    }
+}
+
+// Table holds the values of all the positions of a material signature. The
+// white king is mirrored to files a-d, and to ranks 1-4 without pawns.
+pub struct Table {
+    pub name: String,
+    pieces: Vec<Piece>,
+    pawns: bool,
+    values: Vec<i16>,
+}
+
+impl Table {
+
+    fn new(name: &str) -> Option<Table> {
+        let mut pieces = parse_name(name)?;
+
+        // White king, black king, then the others as named
+        let bk = pieces.iter().rposition(|&pc| pc == B_KING)?;
+        let king = pieces.remove(bk);
+        pieces.insert(1, king);
+
+        // En passant is not encoded
+        if pieces.contains(&W_PAWN) && pieces.contains(&B_PAWN) {
+            return None;
+        }
+
+        let pawns = pieces.iter().any(|pc| pc.piece_type() == PAWN);
+        Some(Table { name: material_name(&pieces), pieces, pawns, values: Vec::new() })
+    }
+
+    fn king_squares(&self) -> usize {
+        if self.pawns { 32 } else { 16 }
+    }
+
+    pub fn size(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    fn index(&self, stm: Color, squares: &[Square]) -> usize {
+        let wk = squares[0];
+        let mut flip = 0;
+
+        if wk.file() >= FILE_E {
+            flip ^= 7;
+        }
+        if !self.pawns && wk.rank() >= RANK_5 {
+            flip ^= 56;
+        }
+
+        let wk = wk.0 ^ flip;
+        let mut idx = stm.0 as usize * self.king_squares() + (wk / 8 * 4 + wk % 8) as usize;
+
+        for s in &squares[1..self.pieces.len()] {
+            idx = idx * 64 + (s.0 ^ flip) as usize;
+        }
+        idx
+    }
+
+    fn decode(&self, mut idx: usize) -> (Color, [Square; MAX_TB_PIECES]) {
+        let mut squares = [Square::NONE; MAX_TB_PIECES];
+
+        for i in (1..self.pieces.len()).rev() {
+            squares[i] = Square((idx % 64) as u32);
+            idx /= 64;
+        }
+
+        let wk = idx % self.king_squares();
+        squares[0] = Square((wk / 4 * 8 + wk % 4) as u32);
+
+        (Color((idx / self.king_squares()) as u32), squares)
+    }
+
+    // probe() returns the value of a position of the table, the pieces in
+    // any order.
+    fn probe(&self, pieces: &[Piece], squares: &[Square], stm: Color) -> i16 {
+        let mut ordered = [Square::NONE; MAX_TB_PIECES];
+        let mut used = [false; MAX_TB_PIECES];
+
+        for (i, &pc) in self.pieces.iter().enumerate() {
+            let j = (0..pieces.len()).find(|&j| !used[j] && pieces[j] == pc).unwrap();
+            used[j] = true;
+            ordered[i] = squares[j];
+        }
+
+        self.values[self.index(stm, &ordered)]
+    }
+
+    // generate() computes the values by retrograde analysis. Checkmates,
+    // and captures or promotions which leave the table (looked up in the
+    // already generated 'tables'), are the seeds. Positions are then
+    // settled by increasing distance to mate: a predecessor of a lost
+    // position is won, a position whose quiet moves all lead to won
+    // positions for the opponent is lost, unless a capture escapes.
+    // Positions never settled are draws.
+    fn generate(&mut self, tables: &Tablebases) {
+
+        let size = self.size();
+        let mut values = vec![UNKNOWN; size];
+        let mut remaining = vec![0u8; size];
+        let mut best_capture = vec![UNKNOWN; size];
+        let mut buckets: Vec<Vec<(u32, i16)>> = Vec::new();
+
+        fn push(buckets: &mut Vec<Vec<(u32, i16)>>, ply: usize, idx: usize, v: i16) {
+            if buckets.len() <= ply {
+                buckets.resize(ply + 1, Vec::new());
+            }
+            buckets[ply].push((idx as u32, v));
+        }
+
+        for idx in 0..size {
+            let (stm, squares) = self.decode(idx);
+            let board = match Board::new(&self.pieces, &squares[..self.pieces.len()]) {
+                Some(board) if !board.in_check(!stm) => board,
+                _ => {
+                    values[idx] = INVALID;
+                    continue;
+                }
+            };
+
+            let mut legal = 0;
+            let mut quiet = 0;
+            let mut best = UNKNOWN;
+
+            board.for_each_move(stm, |child, is_quiet| {
+                legal += 1;
+                if is_quiet {
+                    quiet += 1;
+                } else {
+                    let v = tables.probe_pieces(&child.pieces[..child.n], &child.squares[..child.n], !stm)
+                        .expect("missing sub-table");
+                    best = better(after_move(v), best);
+                }
+            });
+
+            remaining[idx] = quiet;
+            best_capture[idx] = best;
+
+            if legal == 0 {
+                if board.in_check(stm) {
+                    push(&mut buckets, 0, idx, -1);
+                } else {
+                    values[idx] = TB_DRAW;
+                }
+            } else if best != UNKNOWN && best > 0 {
+                push(&mut buckets, best as usize, idx, best);
+            } else if quiet == 0 {
+                if best == TB_DRAW {
+                    values[idx] = TB_DRAW;
+                } else {
+                    push(&mut buckets, (-best - 1) as usize, idx, best);
+                }
+            }
+        }
+
+        let mut ply = 0;
+        while ply < buckets.len() {
+            for (idx, v) in std::mem::take(&mut buckets[ply]) {
+                let idx = idx as usize;
+                if values[idx] != UNKNOWN {
+                    continue;
+                }
+                values[idx] = v;
+
+                let (stm, squares) = self.decode(idx);
+                let board = Board::new(&self.pieces, &squares[..self.pieces.len()]).unwrap();
+
+                board.for_each_unmove(!stm, |pred| {
+                    if pred.in_check(stm) {
+                        return;
+                    }
+                    let pred_idx = self.index(!stm, &pred.squares);
+                    if values[pred_idx] != UNKNOWN {
+                        return;
+                    }
+
+                    if v < 0 {
+                        push(&mut buckets, ply + 1, pred_idx, (ply + 1) as i16);
+                    } else {
+                        debug_assert!(remaining[pred_idx] > 0);
+                        remaining[pred_idx] -= 1;
+                        let capture = best_capture[pred_idx];
+                        if remaining[pred_idx] == 0 && (capture == UNKNOWN || capture < 0) {
+                            let len = if capture == UNKNOWN { ply + 1 } else { (ply + 1).max((-capture - 1) as usize) };
+                            push(&mut buckets, len, pred_idx, -(len as i16) - 1);
+                        }
+                    }
+                });
+            }
+            ply += 1;
+        }
+
+        for v in values.iter_mut() {
+            if *v == UNKNOWN {
+                *v = TB_DRAW;
+            }
+        }
+
+        self.values = values;
+    }
+
+    // max_value() returns the longest win of the table, in plies
+    pub fn max_value(&self) -> i16 {
+        self.values.iter().copied().filter(|&v| v != INVALID).max().unwrap_or(TB_DRAW)
+    }
+}
+
+// Tablebases is the set of generated tables. The material keys of the
+// positions of each table, colors swapped or not, map to the table index,
+// so that a probe does not build the name of the material.
+pub struct Tablebases {
+    tables: Vec<Table>,
+    keys: Vec<(Key, usize, bool)>,
+}
+
+impl Default for Tablebases {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tablebases {
+
+    pub const fn new() -> Tablebases {
+        Tablebases { tables: Vec::new(), keys: Vec::new() }
+    }
+
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.tables.iter().map(|t| t.pieces.len()).max().unwrap_or(0)
+    }
+
+    fn find(&self, name: &str) -> Option<&Table> {
+        self.tables.iter().find(|t| t.name == name)
+    }
+
+    // index_keys() rebuilds the map of the material keys, after tables have
+    // been added.
+    fn index_keys(&mut self) {
+        self.keys.clear();
+        for (i, table) in self.tables.iter().enumerate() {
+            let key = material_key(&table.pieces, false);
+            let swapped_key = material_key(&table.pieces, true);
+            self.keys.push((key, i, false));
+            if swapped_key != key {
+                self.keys.push((swapped_key, i, true));
+            }
+        }
+    }
+
+    fn find_key(&self, key: Key) -> Option<(&Table, bool)> {
+        self.keys.iter()
+            .find(|&&(k, _, _)| k == key)
+            .map(|&(_, i, swapped)| (&self.tables[i], swapped))
+    }
+
+    // generate() builds the table of a material signature, generating first
+    // the tables reached by captures and promotions.
+    pub fn generate(&mut self, name: &str) -> Result<(), String> {
+
+        let pieces = parse_name(name).ok_or(format!("Invalid material: {}", name))?;
+        let (canonical, _) = canonical_name(&pieces);
+        if pieces.len() == 2 || self.find(&canonical).is_some() {
+            return Ok(());
+        }
+
+        let mut table = Table::new(&canonical)
+            .ok_or(format!("Unsupported material: {} (pawns on both sides)", name))?;
+
+        for i in 2..table.pieces.len() {
+            let mut sub = table.pieces.clone();
+            let pc = sub.remove(i);
+            self.generate(&material_name(&sub))?;
+
+            if pc.piece_type() == PAWN {
+                for pt in [QUEEN, ROOK, BISHOP, KNIGHT] {
+                    sub.insert(i, Piece::make(pc.color(), pt));
+                    self.generate(&material_name(&sub))?;
+                    sub.remove(i);
+                }
+            }
+        }
+
+        table.generate(self);
+        self.tables.push(table);
+        self.index_keys();
+        Ok(())
+    }
+
+    // probe_pieces() returns the value of a position given by its pieces,
+    // or None if there is no table for it.
+    fn probe_pieces(&self, pieces: &[Piece], squares: &[Square], stm: Color) -> Option<i16> {
+        self.probe_material(material_key(pieces, false), pieces, squares, stm)
+    }
+
+    // probe_material() is probe_pieces() with the material key already
+    // known. Colors are swapped in place, without allocating.
+    fn probe_material(&self, key: Key, pieces: &[Piece], squares: &[Square], stm: Color) -> Option<i16> {
+
+        if pieces.len() == 2 {
+            return Some(TB_DRAW);
+        }
+
+        let (table, swapped) = self.find_key(key)?;
+
+        if swapped {
+            let mut swapped_pieces = [NO_PIECE; MAX_TB_PIECES];
+            let mut swapped_squares = [Square::NONE; MAX_TB_PIECES];
+            for i in 0..pieces.len() {
+                swapped_pieces[i] = !pieces[i];
+                swapped_squares[i] = Square(squares[i].0 ^ 56);
+            }
+            let n = pieces.len();
+            Some(table.probe(&swapped_pieces[..n], &swapped_squares[..n], !stm))
+        } else {
+            Some(table.probe(pieces, squares, stm))
+        }
+    }
+
+    // probe() returns the value of the position, if it is in the tables
+    pub fn probe(&self, pos: &Position) -> Option<i16> {
+
+        if popcount(pos.pieces()) as usize > self.max_pieces()
+            || !pos.variant().is_chess()
+            || pos.castling_right(WHITE) | pos.castling_right(BLACK) != NO_CASTLING
+            || pos.ep_square() != Square::NONE {
+            return None;
+        }
+
+        let mut pieces = [NO_PIECE; MAX_TB_PIECES];
+        let mut squares = [Square::NONE; MAX_TB_PIECES];
+        let mut n = 0;
+        for s in pos.pieces() {
+            pieces[n] = pos.piece_on(s);
+            squares[n] = s;
+            n += 1;
+        }
+        self.probe_material(pos.material_key(), &pieces[..n], &squares[..n], pos.side_to_move())
+    }
+
+    // probe_root() returns the best move of the position and its value, if
+    // the positions after every legal move are in the tables.
+    pub fn probe_root(&self, pos: &mut Position) -> Option<(Move, i16)> {
+
+        self.probe(pos)?;
+
+        let mut best: Option<(Move, i16)> = None;
+
+        for m in &pos.legal_moves() {
+            pos.do_move(m);
+            let v = if pos.legal_moves().is_empty() {
+                Some(if pos.checkers() != 0 { -1 } else { TB_DRAW })
+            } else {
+                self.probe(pos)
+            };
+            pos.undo_move(m);
+
+            let v = after_move(v?);
+            if best.is_none_or(|(_, b)| rank(v) > rank(b)) {
+                best = Some((m, v));
+            }
+        }
+
+        best
+    }
+
+    // save() writes the tables to a file: a magic header, the number of
+    // tables, then for each one its name and values.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(TB_MAGIC);
+        bytes.extend_from_slice(&(self.tables.len() as u32).to_le_bytes());
+
+        for table in &self.tables {
+            bytes.push(table.name.len() as u8);
+            bytes.extend_from_slice(table.name.as_bytes());
+            bytes.extend_from_slice(&(table.values.len() as u64).to_le_bytes());
+            for v in &table.values {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        fs::write(path, bytes).map_err(|e| format!("Unable to write {}: {}", path, e))
+    }
+
+    // load() reads the tables of a file written by save(), replacing the
+    // tables of the same material.
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+
+        let bytes = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        let corrupted = || format!("Corrupted tablebase file {}", path);
+        let mut cursor = 0;
+        let mut take = |n: usize| {
+            let chunk = bytes.get(cursor..cursor + n);
+            cursor += n;
+            chunk
+        };
+
+        if take(8) != Some(&TB_MAGIC[..]) {
+            return Err(corrupted());
+        }
+        let count = u32::from_le_bytes(take(4).ok_or_else(corrupted)?.try_into().unwrap());
+
+        for _ in 0..count {
+            let len = take(1).ok_or_else(corrupted)?[0] as usize;
+            let name = String::from_utf8_lossy(take(len).ok_or_else(corrupted)?).to_string();
+            let mut table = Table::new(&name).ok_or_else(corrupted)?;
+            let size = u64::from_le_bytes(take(8).ok_or_else(corrupted)?.try_into().unwrap()) as usize;
+            if size != table.size() {
+                return Err(corrupted());
+            }
+
+            table.values = take(2 * size).ok_or_else(corrupted)?
+                .chunks_exact(2)
+                .map(|v| i16::from_le_bytes([v[0], v[1]]))
+                .collect();
+
+            self.tables.retain(|t| t.name != table.name);
+            self.tables.push(table);
+            self.index_keys();
+        }
+
+        Ok(())
+    }
+}
+
+// The tables used by the search, shared by all the threads
+pub static TABLEBASES: RwLock<Tablebases> = RwLock::new(Tablebases::new());
+
+// Number of pieces of the largest loaded table, checked before locking
+static MAX_PIECES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn probe(pos: &Position) -> Option<i16> {
+    if popcount(pos.pieces()) as usize > MAX_PIECES.load(Ordering::Relaxed) {
+        return None;
+    }
+    TABLEBASES.read().unwrap().probe(pos)
+}
+
+pub fn probe_root(pos: &mut Position) -> Option<(Move, i16)> {
+    if popcount(pos.pieces()) as usize > MAX_PIECES.load(Ordering::Relaxed) {
+        return None;
+    }
+    TABLEBASES.read().unwrap().probe_root(pos)
+}
+
+fn update_max_pieces(tb: &Tablebases) {
+    MAX_PIECES.store(tb.max_pieces(), Ordering::Relaxed);
+}
+
+// tbgen() is called when engine receives the "tbgen" command, followed by
+// material signatures ("tbgen KQvKR KBNvK"), or none for the default set.
+pub fn tbgen(args: &str) {
+    let mut tb = TABLEBASES.write().unwrap();
+    let names: Vec<&str> = if args.is_empty() { DEFAULT_TABLES.to_vec() } else { args.split_whitespace().collect() };
+
+    for name in names {
+        match tb.generate(name) {
+            Ok(()) => println!("info string {} generated", name),
+            Err(e) => println!("info string {}", e),
+        }
+    }
+    update_max_pieces(&tb);
+}
+
+// tbsave() and tbload() are called when engine receives the "tbsave" and
+// "tbload" commands, followed by a file name.
+pub fn tbsave(args: &str) {
+    if let Err(e) = TABLEBASES.read().unwrap().save(args) {
+        println!("info string {}", e);
+    }
+}
+
+pub fn tbload(args: &str) {
+    let mut tb = TABLEBASES.write().unwrap();
+    match tb.load(args) {
+        Ok(()) => println!("info string {} tables loaded", tb.tables().len()),
+        Err(e) => println!("info string {}", e),
+    }
+    update_max_pieces(&tb);
+}
+
+#[cfg(test)]
+mod tablebase_test {
+
+    use super::*;
+
+    fn probe_fen(tb: &Tablebases, fen: &str) -> Option<i16> {
+        let mut pos = Position::new();
+        pos.set(fen, false);
+        tb.probe(&pos)
+    }
+
+    #[test]
+    fn longest_mates() {
+        let mut tb = Tablebases::new();
+        tb.generate("KQvK").unwrap();
+        tb.generate("KRvK").unwrap();
+
+        // Mate in 10 moves with the queen, in 16 with the rook
+        assert_eq!(tb.find("KQvK").unwrap().max_value(), 19);
+        assert_eq!(tb.find("KRvK").unwrap().max_value(), 31);
+
+        assert_eq!(probe_fen(&tb, "6k1/8/6K1/8/8/8/8/Q7 w - - 0 1"), Some(1));
+        assert!(probe_fen(&tb, "6k1/8/6K1/8/8/8/8/Q7 b - - 0 1").unwrap() < 0);
+        assert_eq!(probe_fen(&tb, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(TB_DRAW));
+        assert_eq!(probe_fen(&tb, "8/8/8/8/8/8/8/k1K5 w - - 0 1"), Some(TB_DRAW));
+        assert_eq!(probe_fen(&tb, "8/8/8/8/8/8/8/R3K2k w Q - 0 1"), None);
+
+        // Colors swapped
+        assert_eq!(probe_fen(&tb, "q7/8/8/8/8/6k1/8/6K1 b - - 0 1"), Some(1));
+    }
+
+    #[test]
+    fn pawn_endings() {
+        let mut tb = Tablebases::new();
+        tb.generate("KPvK").unwrap();
+        assert!(tb.find("KQvK").is_some() && tb.find("KNvK").is_some());
+
+        assert!(probe_fen(&tb, "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap() > 0);
+        assert!(probe_fen(&tb, "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap() < 0);
+        assert_eq!(probe_fen(&tb, "k7/8/8/8/8/8/P7/K7 w - - 0 1"), Some(TB_DRAW));
+        assert_eq!(probe_fen(&tb, "8/8/8/8/8/4k3/4P3/4K3 w - - 0 1"), Some(TB_DRAW));
+        assert!(probe_fen(&tb, "8/8/8/8/8/8/4p3/k5K1 b - - 0 1").unwrap() > 0);
+
+        // En passant is not encoded, so pawns cannot be on both sides
+        assert!(tb.generate("KPvKP").is_err());
+        assert!(probe_fen(&tb, "4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1").is_none());
+    }
+
+    #[test]
+    fn root_move_and_serialization() {
+        let mut tb = Tablebases::new();
+        tb.generate("KRvK").unwrap();
+
+        let mut pos = Position::new();
+        pos.set("6k1/8/6K1/8/8/8/8/R7 w - - 0 1", false);
+        let (m, v) = tb.probe_root(&mut pos).unwrap();
+        assert_eq!((m, v), (Move::make(Square::A1, Square::A8), 1));
+
+        let path = std::env::temp_dir().join("snowhead_tb_test.bin");
+        let path = path.to_str().unwrap();
+        tb.save(path).unwrap();
+
+        let mut loaded = Tablebases::new();
+        loaded.load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.tables().len(), 1);
+        assert_eq!(loaded.probe(&pos), Some(1));
+        assert_eq!(loaded.probe_root(&mut pos), Some((m, v)));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::position::Position;
+use crate::position::epd::Epd;
+use crate::position::fen::is_chess960_fen;
+use crate::search::Thread;
+use crate::types::r#move::Move;
+use crate::uci::parse_limits;
+
+use std::fs;
+use std::time::Instant;
+
+// TestResult holds the outcome of a test suite run
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TestResult {
+    pub solved: usize,
+    pub total: usize,
+    pub nodes: u64,
+    pub time: u128,
+}
+
+// solves() checks the move found by the search against the bm (best moves)
+// and am (avoid moves) operations of an EPD record. Returns None if the
+// record has neither, so that it is not counted.
+pub fn solves(pos: &Position, epd: &Epd, m: Move) -> Option<bool> {
+
+    let parse = |opcode| epd.operation(opcode)
+        .map(|operands| operands.iter().map(|s| pos.parse_move(s)).collect::<Vec<_>>());
+
+    let best_moves = parse("bm");
+    let avoid_moves = parse("am");
+
+    if best_moves.is_none() && avoid_moves.is_none() {
+        return None;
+    }
+
+    Some(best_moves.is_none_or(|moves| moves.contains(&m))
+        && avoid_moves.is_none_or(|moves| !moves.contains(&m)))
+}
+
+// run_testsuite() searches every position of the EPD records in 'lines'
+// with the given limits ("movetime 1000", "depth 8", "nodes 100000"),
+// printing for each one whether the move found solves it, then the totals.
+pub fn run_testsuite(lines: &str, limits: &str, thread: &mut Thread) -> TestResult {
+
+    let mut result = TestResult::default();
+    let start = Instant::now();
+    let mut pos = Position::new();
+
+    for line in lines.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let epd = match Epd::parse(line) {
+            Some(epd) => epd,
+            None => {
+                println!("Invalid EPD: {}", line);
+                continue;
+            }
+        };
+
+        pos.init_states();
+        pos.set_epd(&epd, is_chess960_fen(&epd.fen));
+
+        thread.init_time(parse_limits(limits), pos.side_to_move(), pos.game_ply());
+        thread.init();
+        thread.search(&mut pos);
+        result.nodes += thread.nodes_searched();
+
+        let m = thread.best_move_found();
+        let id = epd.id().unwrap_or("").to_string();
+        let expected = ["bm", "am"].iter()
+            .filter_map(|&opcode| epd.operation(opcode).map(|ops| format!("{} {}", opcode, ops.join(" "))))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        match solves(&pos, &epd, m) {
+            Some(solved) => {
+                result.total += 1;
+                if solved {
+                    result.solved += 1;
+                }
+                println!(
+                    "{} {}: {} {} ({})",
+                    result.total, id,
+                    if solved { "solved" } else { "unsolved" },
+                    pos.move_to_san(m), expected
+                );
+            }
+            None => println!("{}: no bm or am operation, skipped", id),
+        }
+    }
+
+    result.time = start.elapsed().as_millis();
+
+    println!("\n===========================");
+    println!("Solved       : {} / {}", result.solved, result.total);
+    println!("Nodes        : {}", result.nodes);
+    println!("Total time   : {} ms", result.time);
+
+    result
+}
+
+// testsuite() is called when engine receives the "testsuite" command,
+// e.g. "testsuite wac.epd movetime 1000". The search limits default to
+// one second per position.
+pub fn testsuite(args: &str, thread: &mut Thread) {
+
+    let (file, limits) = match args.split_once(char::is_whitespace) {
+        Some((file, limits)) => (file, limits.trim()),
+        None => (args, "movetime 1000"),
+    };
+
+    match fs::read_to_string(file) {
+        Ok(lines) => { run_testsuite(&lines, limits, thread); }
+        Err(e) => println!("Unable to read {}: {}", file, e),
+    }
+}
+
+#[cfg(test)]
+mod testsuite_test {
+
+    use super::*;
+
+    #[test]
+    fn run_mate_suite() {
+        let lines = "\
+            # Mates in one\n\
+            6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - bm Ra8#; id \"back rank\";\n\
+            k7/8/1K6/8/8/8/8/6Q1 w - - am Qb1; id \"stalemate trap\";\n\
+            8/8/8/8/8/8/8/K6k w - - id \"no operation\";\n";
+
+        let mut thread = Thread::new(16);
+        let result = run_testsuite(lines, "depth 4", &mut thread);
+
+        assert_eq!(result.total, 2);
+        assert_eq!(result.solved, 2);
+        assert!(result.nodes > 0);
+    }
+}
@@ -1,10 +1,30 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::mem;
+use std::thread;
 
 use crate::zobrist::Key;
 use crate::types::score::{Value, Depth};
 use crate::types::r#move::Move;
 
-const MAX_TT_SIZE_MB: usize = 1024;
+// Tables of at least this size are cleared by all the available threads
+const PARALLEL_CLEAR_MB: usize = 64;
+
+// Header of the hash files: the magic, the format version, the number of
+// entries and the generation of the table
+const TT_MAGIC: &[u8; 8] = b"SNOWTT\0\0";
+const TT_VERSION: u32 = 2;
+const TT_HEADER_LEN: usize = 8 + 4 + 8 + 1;
+const TT_ENTRY_LEN: usize = 8;
+
+// The flag of an entry takes the low 2 bits of its flag byte and the
+// generation of the search which saved it the 6 high bits
+const FLAG_MASK: u8 = 0x3;
+const GENERATION_SHIFT: u32 = 2;
+
+// An entry of the current search is only replaced by the one of another
+// position if it is not much deeper
+const REPLACE_DEPTH_MARGIN: i32 = 4;
 
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -20,7 +40,7 @@ pub enum TTFlag {
 pub struct TTEntry{
     key16: u16,
     value16: i16,
-    flag: TTFlag,
+    genflag8: u8,
     depth8: i8,
     move16: u16,
     
@@ -28,28 +48,60 @@ pub struct TTEntry{
 
 #[derive(Debug, Clone)]
 pub struct TranspositionTable {
-    table: Vec<TTEntry>
+    table: Vec<TTEntry>,
+    generation: u8,
 }
 
 impl TranspositionTable {
     pub fn new(mb_size: usize) -> TranspositionTable {
-        
-        TranspositionTable {
-            table: vec![Default::default(); Self::num_entries(mb_size)]
-        }
-        
+        let mut tt = TranspositionTable { table: Vec::new(), generation: 0 };
+        tt.resize(mb_size);
+        tt
+    }
+
+    // resize() sets the size of the table in MB, which does not need to be
+    // a power of two. The old table is freed before the new one is
+    // allocated, and the entries are initialized by all the threads for
+    // large tables.
+    pub fn resize(&mut self, mb_size: usize) {
+        let len = Self::num_entries(mb_size);
+
+        self.table = Vec::new();
+        let mut table = Vec::with_capacity(len);
+        Self::for_each_chunk(&mut table.spare_capacity_mut()[..len], |chunk| {
+            for entry in chunk {
+                entry.write(TTEntry::default());
+            }
+        });
+
+        // SAFETY: the first len entries have been initialized above
+        unsafe { table.set_len(len) };
+        self.table = table;
     }
 
+    // save() stores an entry, unless the slot holds a deeper entry of another
+    // position saved during the current search. Entries of the previous
+    // searches are always replaced.
     pub fn save(&mut self, key: Key, value: Value, flag: TTFlag, depth: Depth, m: Move) {
         let idx = self.idx(key);
-        self.table[idx] = TTEntry::new(key, value, flag, depth, m);
+        let tte = self.table[idx];
+
+        if tte.key16 == key as u16
+            || !tte.is_from(self.generation)
+            || depth.0 > tte.depth8 as i32 - REPLACE_DEPTH_MARGIN
+        {
+            self.table[idx] = TTEntry::new(key, value, flag, depth, m, self.generation);
+        }
     }
 
     pub fn probe(&mut self, key: Key) -> (bool, Value, TTFlag, Depth, Move) {
         
         let idx = self.idx(key);
         let tte = self.table[idx];
-        let tt_hit = tte.flag != TTFlag::NONE;
+
+        // The index comes from the high bits of the key and the check from
+        // the low ones, so that entries of other positions are not hits
+        let tt_hit = tte.get_flag() != TTFlag::NONE && tte.key16 == key as u16;
         if tt_hit {
             return (true, tte.get_value(), tte.get_flag(), tte.get_depth(), tte.get_move());
         }
@@ -66,29 +118,126 @@ impl TranspositionTable {
 
     }
 
+    // generation() is the number of searches made with the table since it
+    // was last cleared, modulo 256. It is kept in the hash files so that the
+    // entries of a resumed analysis are still of the current search.
+    pub fn generation(&self) -> u8 {
+        self.generation
+    }
+
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    // clear() empties the table, with all the available threads for large
+    // tables.
     pub fn clear(&mut self) {
-        for idx in 0..self.len() {
-            self.table[idx] = Default::default();
+        Self::for_each_chunk(&mut self.table, |chunk| chunk.fill(TTEntry::default()));
+        self.generation = 0;
+    }
+
+    // save_file() writes the table to a file: a header with the magic, the
+    // format version, the number of entries and the generation, then the
+    // entries in little endian.
+    pub fn save_file(&self, path: &str) -> Result<(), String> {
+        let write_error = |e| format!("Unable to write {}: {}", path, e);
+        let mut writer = BufWriter::new(File::create(path).map_err(write_error)?);
+
+        let mut header = Vec::with_capacity(TT_HEADER_LEN);
+        header.extend_from_slice(TT_MAGIC);
+        header.extend_from_slice(&TT_VERSION.to_le_bytes());
+        header.extend_from_slice(&(self.table.len() as u64).to_le_bytes());
+        header.push(self.generation);
+        writer.write_all(&header).map_err(write_error)?;
+
+        for entry in &self.table {
+            writer.write_all(&entry.to_bytes()).map_err(write_error)?;
+        }
+        writer.flush().map_err(write_error)
+    }
+
+    // load_file() replaces the table by the one of a file written by
+    // save_file(), resizing it to the size of the file. The table is left
+    // untouched if the file cannot be read.
+    pub fn load_file(&mut self, path: &str) -> Result<(), String> {
+        let read_error = |e| format!("Unable to read {}: {}", path, e);
+        let corrupted = || format!("Corrupted hash file {}", path);
+        let file_len = fs::metadata(path).map_err(read_error)?.len();
+        let mut reader = BufReader::new(File::open(path).map_err(read_error)?);
+
+        let mut header = [0u8; TT_HEADER_LEN];
+        reader.read_exact(&mut header).map_err(|_| corrupted())?;
+        if &header[..8] != TT_MAGIC {
+            return Err(corrupted());
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != TT_VERSION {
+            return Err(format!("Unsupported hash file version {} in {}", version, path));
+        }
+        let len = u64::from_le_bytes(header[12..20].try_into().unwrap());
+        let generation = header[20];
+
+        // The size is checked against the file before allocating the table
+        if len == 0 || len.checked_mul(TT_ENTRY_LEN as u64).and_then(|n| n.checked_add(TT_HEADER_LEN as u64)) != Some(file_len) {
+            return Err(corrupted());
+        }
+
+        let mut table = Vec::with_capacity(len as usize);
+        let mut bytes = [0u8; TT_ENTRY_LEN];
+        for _ in 0..len {
+            reader.read_exact(&mut bytes).map_err(|_| corrupted())?;
+            table.push(TTEntry::from_bytes(bytes));
         }
 
+        self.table = table;
+        self.generation = generation;
+        Ok(())
+    }
+
+    // prefetch() brings the entry of the given key into the cache, so that
+    // it is at hand when the node is probed.
+    #[inline]
+    pub fn prefetch(&self, key: Key) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let entry = &self.table[self.idx(key)] as *const TTEntry as *const i8;
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(entry) };
+        }
     }
 
+    // idx() maps the key to an entry by taking the high 64 bits of the
+    // product of the key and the number of entries, which works for any
+    // number of entries and avoids a division.
     fn idx(&self, key: Key) -> usize {
-        key as usize % self.table.len()
+        ((key as u128 * self.table.len() as u128) >> 64) as usize
     }
-    
-    // // This method clears up the table
-    // fn resize(&mut self, mb_size: usize) {
-    //     self.table = vec![Default::default(); Self::num_entries(mb_size)]
-    // }
-    
-    fn num_entries(size_mb: usize) -> usize {
-        if size_mb > MAX_TT_SIZE_MB {
-            eprintln!("Transposition table size exceeds max size.\n
-                 Size will be set to {} MB", MAX_TT_SIZE_MB);
-            return MAX_TT_SIZE_MB * 1024 * 1024 / mem::size_of::<TTEntry>();
+
+    // for_each_chunk() splits the entries among all the available threads,
+    // when the table is large enough to be worth it, and calls f() on
+    // each chunk.
+    fn for_each_chunk<T: Send>(entries: &mut [T], f: impl Fn(&mut [T]) + Sync) {
+        let threads = if entries.len() * mem::size_of::<TTEntry>() >= PARALLEL_CLEAR_MB * 1024 * 1024 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            1
+        };
+
+        if threads == 1 {
+            f(entries);
+            return;
         }
-        size_mb * 1024 * 1024 / mem::size_of::<TTEntry>()
+
+        let chunk_len = entries.len().div_ceil(threads);
+        thread::scope(|s| {
+            for chunk in entries.chunks_mut(chunk_len) {
+                s.spawn(|| f(chunk));
+            }
+        });
+    }
+
+    fn num_entries(size_mb: usize) -> usize {
+        size_mb.max(1) * 1024 * 1024 / mem::size_of::<TTEntry>()
     }
     
     
@@ -98,14 +247,14 @@ impl TranspositionTable {
 
 impl TTEntry {
     
-    pub fn new(key: Key, value: Value, flag: TTFlag, depth: Depth, m: Move) -> TTEntry {
+    pub fn new(key: Key, value: Value, flag: TTFlag, depth: Depth, m: Move, generation: u8) -> TTEntry {
         
         TTEntry {
             key16: key as u16, 
             value16: value.0 as i16, 
-            flag: flag, 
+            genflag8: generation << GENERATION_SHIFT | flag as u8,
             depth8: depth.0 as i8,
-            move16: m.0 as u16
+            move16: m.to_u16()
         }
         
     }
@@ -115,7 +264,18 @@ impl TTEntry {
     }
 
     pub fn get_flag(&self) -> TTFlag {
-        self.flag
+        match self.genflag8 & FLAG_MASK {
+            0 => TTFlag::EXACT,
+            1 => TTFlag::LOWER,
+            2 => TTFlag::UPPER,
+            _ => TTFlag::NONE,
+        }
+    }
+
+    // is_from() tests whether the entry was saved during the search of the
+    // given generation, which is compared modulo 64.
+    pub fn is_from(&self, generation: u8) -> bool {
+        self.genflag8 & !FLAG_MASK == generation << GENERATION_SHIFT
     }
 
     pub fn get_depth(&self) -> Depth {
@@ -123,7 +283,24 @@ impl TTEntry {
     }
 
     pub fn get_move(&self) -> Move {
-        Move(self.move16 as u32)
+        Move::from_u16(self.move16)
+    }
+
+    fn to_bytes(self) -> [u8; TT_ENTRY_LEN] {
+        let [k0, k1] = self.key16.to_le_bytes();
+        let [v0, v1] = self.value16.to_le_bytes();
+        let [m0, m1] = self.move16.to_le_bytes();
+        [k0, k1, v0, v1, self.genflag8, self.depth8 as u8, m0, m1]
+    }
+
+    fn from_bytes(b: [u8; TT_ENTRY_LEN]) -> TTEntry {
+        TTEntry {
+            key16: u16::from_le_bytes([b[0], b[1]]),
+            value16: i16::from_le_bytes([b[2], b[3]]),
+            genflag8: b[4],
+            depth8: b[5] as i8,
+            move16: u16::from_le_bytes([b[6], b[7]]),
+        }
     }
 
 }
@@ -134,7 +311,7 @@ impl Default for TTEntry {
         TTEntry {
             key16: 0u16,
             value16: 0i16,
-            flag: TTFlag::NONE,
+            genflag8: TTFlag::NONE as u8,
             depth8: 0i8,
             move16: 0u16
         }
@@ -161,13 +338,88 @@ mod tt_test {
     }
 
     #[test]
-    fn size_mb_is_set_to_limit() {
-        let sizes = [1100, 1500];
-        let mut ttable;
-        for size in sizes {
-            ttable = TranspositionTable::new(size);
-            assert_eq!(MAX_TT_SIZE_MB, ttable.size_mb());
-        } 
+    fn any_size_is_indexed() {
+        let mut ttable = TranspositionTable::new(1);
+        for size in [3, 5, 96] {
+            ttable.resize(size);
+            assert_eq!(size, ttable.size_mb());
+
+            // The keys at both ends of the range map to the first and the
+            // last entries.
+            assert_eq!(ttable.idx(0), 0);
+            assert_eq!(ttable.idx(Key::MAX), ttable.len() - 1);
+        }
+    }
+
+    #[test]
+    fn clear_empties_the_table() {
+        let mut pos = Position::new();
+        pos.set("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", false);
+
+        // 96 MB is cleared in parallel
+        for size in [1, 96] {
+            let mut ttable = TranspositionTable::new(size);
+            ttable.save(pos.key(), Value(42), TTFlag::EXACT, Depth(3), Move::NONE);
+            assert!(ttable.probe(pos.key()).0);
+            ttable.clear();
+            assert!(!ttable.probe(pos.key()).0);
+        }
+    }
+
+    #[test]
+    fn deep_entries_of_the_current_search_are_kept() {
+        // Both keys map to the same entry
+        let (key1, key2) = (0x8000_0000_0000_0001, 0x8000_0000_0000_0002);
+        let mut ttable = TranspositionTable::new(1);
+        assert_eq!(ttable.idx(key1), ttable.idx(key2));
+
+        ttable.new_search();
+        ttable.save(key1, Value(10), TTFlag::EXACT, Depth(8), Move::NONE);
+        ttable.save(key2, Value(20), TTFlag::EXACT, Depth(2), Move::NONE);
+        assert!(ttable.probe(key1).0);
+        assert!(!ttable.probe(key2).0);
+
+        // The same position is always replaced
+        ttable.save(key1, Value(30), TTFlag::UPPER, Depth(1), Move::NONE);
+        assert_eq!(ttable.probe(key1).1, Value(30));
+
+        // So is an entry of a previous search
+        ttable.save(key1, Value(10), TTFlag::EXACT, Depth(8), Move::NONE);
+        ttable.new_search();
+        ttable.save(key2, Value(20), TTFlag::EXACT, Depth(2), Move::NONE);
+        assert!(!ttable.probe(key1).0);
+        assert!(ttable.probe(key2).0);
+    }
+
+    #[test]
+    fn hash_file_round_trip() {
+        let mut pos = Position::new();
+        pos.set("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", false);
+        let m = Move::from_u16(0x1234);
+
+        let mut ttable = TranspositionTable::new(2);
+        ttable.save(pos.key(), Value(-42), TTFlag::LOWER, Depth(-1), m);
+        ttable.new_search();
+        ttable.new_search();
+
+        let path = std::env::temp_dir().join(format!("snowhead-tt-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        ttable.save_file(path).unwrap();
+
+        // The loaded table takes the size and generation of the file
+        let mut loaded = TranspositionTable::new(1);
+        loaded.load_file(path).unwrap();
+        assert_eq!(loaded.size_mb(), 2);
+        assert_eq!(loaded.generation(), 2);
+        assert_eq!(loaded.probe(pos.key()), (true, Value(-42), TTFlag::LOWER, Depth(-1), m));
+
+        // A truncated file is rejected and leaves the table as it was
+        let bytes = fs::read(path).unwrap();
+        fs::write(path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(loaded.load_file(path).is_err());
+        assert_eq!(loaded.size_mb(), 2);
+
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
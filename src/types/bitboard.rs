@@ -30,6 +30,8 @@ pub const RANK_6_BB: Bitboard = Bitboard(0xff0000000000);
 pub const RANK_7_BB: Bitboard = Bitboard(0xff000000000000);
 pub const RANK_8_BB: Bitboard = Bitboard(0xff00000000000000);
 
+pub const CENTER: Bitboard = Bitboard((FILE_D_BB.0 | FILE_E_BB.0) & (RANK_4_BB.0 | RANK_5_BB.0));
+
 impl std::ops::Neg for Bitboard {
     type Output = Bitboard;
     fn neg(self) -> Self {
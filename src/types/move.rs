@@ -1,5 +1,5 @@
 use super::square::{Square, FILE_C, FILE_G};
-use super::piece::{Color, PieceType, KNIGHT, WHITE, BLACK, PIECE_TO_CHAR};
+use super::piece::{Color, PieceType, KNIGHT, KING, WHITE, BLACK, PIECE_TO_CHAR};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MoveType(pub u32);
@@ -8,6 +8,7 @@ pub const NORMAL    : MoveType = MoveType(0);
 pub const PROMOTION : MoveType = MoveType(1 << 14);
 pub const EN_PASSANT : MoveType = MoveType(2 << 14);
 pub const CASTLING  : MoveType = MoveType(3 << 14);
+pub const DROP      : MoveType = MoveType(4 << 14);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Move(pub u32);
@@ -29,15 +30,25 @@ impl Move {
     }
 
     pub const fn move_type(self) -> MoveType {
-        MoveType(self.0 & (3 << 14))
+        MoveType(self.0 & (7 << 14))
     }
 
+    /// promotion_type() returns the promotion piece type. Promotions to a
+    /// king, as allowed by antichess, are flagged in the upper bits.
     pub const fn promotion_type(self) -> PieceType {
+        if self.0 >> 17 != 0 {
+            return KING;
+        }
         PieceType(((self.0 >> 12) & 3) + KNIGHT.0)
     }
 
+    /// drop_type() returns the type of the piece dropped by a drop move
+    pub const fn drop_type(self) -> PieceType {
+        PieceType((self.0 >> 17) & 7)
+    }
+
     pub fn is_ok(self) -> bool {
-        self.from() != self.to()
+        self.from() != self.to() || self.move_type() == DROP
     }
 
     pub const fn make(from: Square, to: Square) -> Move {
@@ -45,6 +56,9 @@ impl Move {
     }
 
     pub const fn make_prom(from: Square, to: Square, pt: PieceType) -> Move {
+        if pt.0 == KING.0 {
+            return Move(PROMOTION.0 + (1 << 17) + (from.0 << 6) + to.0);
+        }
         Move(PROMOTION.0 + ((pt.0 - KNIGHT.0) << 12) + (from.0 << 6) + to.0)
     }
 
@@ -52,12 +66,44 @@ impl Move {
         Move(mt.0 + (from.0 << 6) + to.0)
     }
 
+    /// make_drop() creates a move dropping a piece of the given type from
+    /// the hand. A drop has the same 'from' and 'to' squares.
+    pub const fn make_drop(pt: PieceType, to: Square) -> Move {
+        Move((pt.0 << 17) + DROP.0 + (to.0 << 6) + to.0)
+    }
+
+    /// to_u16() packs the move in 16 bits, as stored in the transposition
+    /// table. A drop, the only move with equal 'from' and 'to' squares,
+    /// keeps its piece type in the upper bits. A promotion to a king does
+    /// not fit and is packed as no move.
+    pub const fn to_u16(self) -> u16 {
+        if self.move_type().0 == DROP.0 {
+            ((self.drop_type().0 << 12) + (self.0 & 0xfff)) as u16
+        } else if self.0 >> 17 != 0 {
+            Move::NONE.0 as u16
+        } else {
+            self.0 as u16
+        }
+    }
+
+    pub const fn from_u16(m: u16) -> Move {
+        let m = Move(m as u32);
+        if m.from().0 == m.to().0 && m.0 >> 12 != 0 {
+            Move::make_drop(PieceType(m.0 >> 12), m.to())
+        } else {
+            m
+        }
+    }
+
     pub fn to_string(self, chess960: bool) -> String {
         let from: Square = self.from();
         let mut to: Square = self.to();
 
         if self == Move::NONE { "(none)".to_string() }
         else if self == Move::NULL { "0000".to_string() }
+        else if self.move_type() == DROP {
+            format!("{}@{}", PIECE_TO_CHAR[self.drop_type().0 as usize], to.to_string())
+        }
         else {
             if self.move_type() == CASTLING && !chess960 {
                 to = Square::make( if to > from { FILE_G } else { FILE_C }, from.rank() );
@@ -4,12 +4,22 @@ use super::piece::{Piece, PIECE_NB, COLOR_NB};
 // pub struct Ply(pub u32);
 
 pub const MAX_MOVES: usize = 256;
+// Drops make crazyhouse positions with more than 256 legal moves, so move
+// lists get this size only in variants with drops
+pub const MAX_DROP_MOVES: usize = 512;
 pub const MAX_PLY: i32 = 128;
 pub const MAX_MATE_PLY: i32 = 128;
 
 #[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
 pub struct Depth(pub i32);
 
+impl Depth {
+    // Depths of the quiescence search entries in the transposition table:
+    // quiet checks are searched at the first ply only.
+    pub const QS_CHECKS   : Depth = Depth(0);
+    pub const QS_NO_CHECKS: Depth = Depth(-1);
+}
+
 impl std::ops::Mul<Depth> for i32 {
     type Output = Depth;
     fn mul(self, rhs: Depth) -> Depth { Depth(self * rhs.0) }
@@ -120,6 +130,31 @@ pub fn mated_in(ply: i32) -> Value {
     -Value::MATE + ply
 }
 
+/// value_to_tt() converts a mate score relative to the root into a score
+/// relative to the node at ply, so that the entry holds the distance to
+/// mate from the position itself
+pub fn value_to_tt(v: Value, ply: i32) -> Value {
+    if v >= Value::MATE_IN_MAX_PLY {
+        v + ply
+    } else if v <= Value::MATED_IN_MAX_PLY {
+        v - ply
+    } else {
+        v
+    }
+}
+
+/// value_from_tt() is the inverse of value_to_tt(), converting the score
+/// of a TT entry back into a score relative to the root
+pub fn value_from_tt(v: Value, ply: i32) -> Value {
+    if v >= Value::MATE_IN_MAX_PLY {
+        v - ply
+    } else if v <= Value::MATED_IN_MAX_PLY {
+        v + ply
+    } else {
+        v
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Score(pub i32);
 
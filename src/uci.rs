@@ -1,14 +1,18 @@
 use crate::types::piece::{WHITE, BLACK};
-use crate::uciset::{UCILimits};
-use crate::movegen::{ExtMove, generate_legal};
+use crate::uciset::{UCILimits, UCIOptions};
+use crate::book::{Book, makebook};
+use crate::tablebase::{tbgen, tbload, tbsave};
 use crate::position::Position;
 use crate::search::Thread;
-use crate::perft::perft;
+use crate::perft::{perft_cmd, perft_divide, perft_suite};
 use crate::tune::eval;
+use crate::testsuite::testsuite;
+use crate::benchmark::bench;
 use crate::tt::{TranspositionTable, TTFlag};
 
 use crate::types::r#move::Move;
-use crate::types::score::{Depth, Value};
+use crate::types::score::Depth;
+use crate::variant::Variant;
 
 
 
@@ -26,7 +30,7 @@ pub const START_FEN: &'static str =
 // or the starting position ("startpos") and then makes the moves given in the
 // following move list ("moves").
 
-fn position(pos: &mut Position, args: &str) {
+fn position(pos: &mut Position, args: &str, variant: Variant, chess960: bool) {
     let fen: &str;
 
     let moves = match args.find("moves") {
@@ -43,7 +47,7 @@ fn position(pos: &mut Position, args: &str) {
     }
 
     pos.init_states();
-    pos.set(fen, pos.is_chess960());
+    pos.set_variant(variant, fen, chess960);
     // pos_data.fen = String::from(fen);
     // pos_data.moves = Vec::new();
 
@@ -55,19 +59,17 @@ fn position(pos: &mut Position, args: &str) {
     let moves = &args[moves+5..].trim();
     let iter = moves.split_whitespace();
     for token in iter {
-        let m = Move::from_string(pos, token);
-        if m == Move::NONE {
+        let Ok(m) = Move::from_uci(pos, token) else {
             break;
-        }
+        };
         pos.do_move(m);
     }
 }
 
-// go() is called when engine receives the "go" UCI command. The function
-// sets the thinking time and other parameters from the input string, then
-// starts the search.
+// parse_limits() reads the search limits of a "go" command, as well as
+// the limits given to the "testsuite" command.
 
-fn go(pos: &mut Position, args: &str, thread: &mut Thread) {
+pub fn parse_limits(args: &str) -> UCILimits {
 
     let mut limits = UCILimits::new(); // This starts the time
     let mut iter = args.split_whitespace();
@@ -96,16 +98,84 @@ fn go(pos: &mut Position, args: &str, thread: &mut Thread) {
         }
     }
 
+    limits
+}
+
+// setoption() is called when engine receives the "setoption" UCI command.
+// The function updates the UCI option ("name") to the given value ("value")
+// and (re)loads the opening book when the book options change. The hash
+// table is resized or cleared by the "Hash" and "Clear Hash" options, and
+// saved to or loaded from "HashFile" by the "Save Hash" and "Load Hash"
+// options, so that a long analysis can be resumed later.
+
+fn setoption(args: &str, options: &mut UCIOptions, book: &mut Option<Book>, thread: &mut Thread) {
+
+    let args = args.trim_start_matches("name").trim();
+    let (name, value) = match args.find(" value") {
+        Some(idx) => (args[..idx].trim(), args[idx + 6..].trim()),
+        None => (args, ""),
+    };
+
+    if !options.set(name, value) {
+        println!("No such option: {}", name);
+        return;
+    }
+
+    let name = name.to_lowercase();
+    if name == "hash" {
+        thread.resize_ttable(options.hash);
+    } else if name == "clear hash" {
+        thread.clear_ttable();
+    } else if name == "save hash" {
+        if let Err(e) = thread.save_ttable(&options.hash_file) {
+            println!("info string {}", e);
+        }
+    } else if name == "load hash" {
+        match thread.load_ttable(&options.hash_file) {
+            Ok(mb) => {
+                options.hash = mb;
+                println!("info string {} MB hash loaded from {}", mb, options.hash_file);
+            }
+            Err(e) => println!("info string {}", e),
+        }
+    }
+
+    if name == "ownbook" || name == "bookfile" {
+        *book = None;
+        if options.own_book {
+            match Book::open(&options.book_file) {
+                Ok(b) => *book = Some(b),
+                Err(e) => println!("info string {}", e),
+            }
+        }
+    }
+}
+
+// go() is called when engine receives the "go" UCI command. The function
+// sets the thinking time and other parameters from the input string, then
+// starts the search, unless the opening book has a move for the position.
+
+fn go(pos: &mut Position, args: &str, thread: &mut Thread, options: &UCIOptions, book: &mut Option<Book>) {
+
+    let limits = parse_limits(args);
+
     if limits.perft > 0 {
-        let nodes = perft::<true>(pos, Depth(limits.perft as i32));
-        println!("Total nodes seached: {}", nodes);
-    } else {
-        thread.init_time(limits, pos.side_to_move(), pos.game_ply());
-        thread.init();
-        thread.search(pos);
+        perft_divide(pos, Depth(limits.perft as i32));
+        return;
     }
 
-    
+    let use_book = options.own_book && pos.variant().is_chess() && pos.game_ply() < 2 * options.book_depth;
+    if let Some(book) = book.as_mut().filter(|_| use_book) {
+        let m = book.probe(pos, options.best_book_move);
+        if m != Move::NONE {
+            println!("bestmove {}", m.to_string(pos.is_chess960()));
+            return;
+        }
+    }
+
+    thread.init_time(limits, pos.side_to_move(), pos.game_ply());
+    thread.init();
+    thread.search(pos);
 }
 
 // cmd_loop() waits for a command from stdin, parses it and calls the
@@ -117,7 +187,9 @@ fn go(pos: &mut Position, args: &str, thread: &mut Thread) {
 
 pub fn cmd_loop() {
     let mut pos = Box::new(Position::new());
-    let mut thread = Thread::new(256);
+    let mut options = UCIOptions::new();
+    let mut thread = Thread::new(options.hash);
+    let mut book: Option<Book> = None;
 
     pos.init_states();
     pos.set(START_FEN, false);
@@ -156,20 +228,30 @@ pub fn cmd_loop() {
 
         match token {
             "quit" | "stop" => {},
-            "ucinewgame" => { thread = Thread::new(256); }
+            "ucinewgame" => thread.clear_ttable(),
             "uci" => {
                 println!("id name Snowhead v0.1.1");
+                options.print();
                 println!("uciok");
             }
-            "go" => go(&mut pos, args, &mut thread),
+            "go" => go(&mut pos, args, &mut thread, &options, &mut book),
+            "setoption" => setoption(args, &mut options, &mut book, &mut thread),
             "position" =>
-                position(&mut pos, args),
+                position(&mut pos, args, options.variant, options.chess960),
             
             "isready" => println!("readyok"),
 
             // Additional custom non-UCI commands
             "d" => pos.print(),
             "eval" => eval(args),
+            "testsuite" => testsuite(args, &mut thread),
+            "bench" => { bench(args); }
+            "perft" => perft_cmd(&mut pos, args),
+            "perftsuite" => perft_suite(args),
+            "makebook" => makebook(args),
+            "tbgen" => tbgen(args),
+            "tbsave" => tbsave(args),
+            "tbload" => tbload(args),
             _ => println!("Unknown command: {} {}", cmd, args)
         }
         if env::args().len() > 1 || token == "quit" {
@@ -181,22 +263,14 @@ pub fn cmd_loop() {
 
 impl Move {
 
-    pub fn from_string(pos: &Position, s: &str) -> Move {
-        if s.len() == 5 {
-        }
-
-        let mut list = [ExtMove {m: Move::NONE, value: Value::ZERO}; 200];
-
-        let _num_moves = generate_legal(&pos, &mut list, 0);
-
-        for ext_move in list {
-            let m = ext_move.m;
-            if s == m.to_string(pos.is_chess960()) {
-                return m;
-            }
-        }
-
-        Move::NONE
+    /// from_uci() converts a move in coordinate notation to the corresponding
+    /// legal move, or returns an error if there is no such move. Drops are
+    /// written with the piece letter, like P@e4.
+    pub fn from_uci(pos: &Position, s: &str) -> Result<Move, String> {
+        pos.legal_moves()
+            .iter()
+            .find(|m| s.eq_ignore_ascii_case(&m.to_string(pos.is_chess960())))
+            .ok_or_else(|| format!("illegal move {} in {}", s, pos.fen()))
     }
 
 }
@@ -1,4 +1,5 @@
 use crate::types::piece::{COLOR_NB};
+use crate::variant::Variant;
 use std::time::SystemTime;
 
 #[derive(Debug, Clone, Copy)]
@@ -35,4 +36,76 @@ impl UCILimits {
         self.mate == 0 && self.movetime == 0 && self.depth == 0
         && self.nodes == 0 && self.perft == 0 && !self.infinite
     }
-}
\ No newline at end of file
+}
+
+// UCIOptions holds the values of the options set by the GUI with the
+// "setoption" command.
+#[derive(Debug, Clone)]
+pub struct UCIOptions {
+    pub own_book: bool,
+    pub book_file: String,
+    pub book_depth: i32,
+    pub best_book_move: bool,
+    pub chess960: bool,
+    pub variant: Variant,
+    pub hash: usize,
+    pub hash_file: String,
+}
+
+impl UCIOptions {
+    pub fn new() -> UCIOptions {
+        UCIOptions {
+            own_book: false,
+            book_file: String::from("book.bin"),
+            book_depth: 255,
+            best_book_move: false,
+            chess960: false,
+            variant: Variant::Chess,
+            hash: 256,
+            hash_file: String::from("hash.bin"),
+        }
+    }
+}
+
+impl Default for UCIOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UCIOptions {
+
+    // print() lists the options in the format of the "uci" command reply
+    pub fn print(&self) {
+        println!("option name Hash type spin default {} min 1 max 33554432", self.hash);
+        println!("option name Clear Hash type button");
+        println!("option name HashFile type string default {}", self.hash_file);
+        println!("option name Save Hash type button");
+        println!("option name Load Hash type button");
+        println!("option name OwnBook type check default {}", self.own_book);
+        println!("option name BookFile type string default {}", self.book_file);
+        println!("option name Book Depth type spin default {} min 1 max 255", self.book_depth);
+        println!("option name Best Book Move type check default {}", self.best_book_move);
+        println!("option name UCI_Chess960 type check default {}", self.chess960);
+        let vars: Vec<String> = Variant::ALL.iter().map(|v| format!("var {}", v)).collect();
+        println!("option name UCI_Variant type combo default {} {}", self.variant, vars.join(" "));
+    }
+
+    // set() updates an option, returning false if there is no such option.
+    // Option names are case insensitive.
+    pub fn set(&mut self, name: &str, value: &str) -> bool {
+        match name.to_lowercase().as_str() {
+            "hash" => self.hash = value.parse().unwrap_or(self.hash).clamp(1, 33554432),
+            "clear hash" | "save hash" | "load hash" => {}
+            "hashfile" => self.hash_file = String::from(value),
+            "ownbook" => self.own_book = value == "true",
+            "bookfile" => self.book_file = String::from(value),
+            "book depth" => self.book_depth = value.parse().unwrap_or(self.book_depth).clamp(1, 255),
+            "best book move" => self.best_book_move = value == "true",
+            "uci_chess960" => self.chess960 = value == "true",
+            "uci_variant" => self.variant = Variant::from_name(value).unwrap_or(self.variant),
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,337 @@
+use crate::types::piece::KING;
+use crate::types::r#move::Move;
+use crate::types::bitboard::{EMPTY_BB, CENTER};
+use crate::types::score::{Value, mate_in, mated_in};
+use crate::zobrist::CHECKS_NB;
+use crate::position::Position;
+use std::fmt;
+
+/// Variant is the set of rules a position is played with. Standard chess
+/// and Chess960 share the same rules, the castling notation aside.
+///
+/// The rules in which a variant differs from chess are queried through the
+/// hooks below, which Position, the move generator and the search test
+/// after is_chess(), so that standard chess keeps its fast path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    #[default]
+    Chess,
+    Crazyhouse,
+    Atomic,
+    Antichess,
+    ThreeCheck,
+    KingOfTheHill,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 6] = [
+        Variant::Chess, Variant::Crazyhouse, Variant::Atomic, Variant::Antichess,
+        Variant::ThreeCheck, Variant::KingOfTheHill,
+    ];
+
+    /// name() returns the name of the variant, as used by UCI_Variant
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Chess => "chess",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Atomic => "atomic",
+            Variant::Antichess => "antichess",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+        }
+    }
+
+    /// pgn_name() returns the name of the variant used by the PGN Variant tag
+    pub fn pgn_name(self) -> &'static str {
+        match self {
+            Variant::Chess => "Standard",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::Atomic => "Atomic",
+            Variant::Antichess => "Antichess",
+            Variant::ThreeCheck => "Three-check",
+            Variant::KingOfTheHill => "King of the Hill",
+        }
+    }
+
+    /// from_name() looks up a variant by its UCI or PGN name
+    pub fn from_name(name: &str) -> Option<Variant> {
+        Variant::ALL.into_iter().find(|v| {
+            v.name().eq_ignore_ascii_case(name) || v.pgn_name().eq_ignore_ascii_case(name)
+        })
+    }
+
+    pub const fn is_chess(self) -> bool {
+        matches!(self, Variant::Chess)
+    }
+
+    /// has_drops() tests whether captured pieces go to the hand of the
+    /// capturing side, from where they can be dropped back on the board.
+    pub const fn has_drops(self) -> bool {
+        matches!(self, Variant::Crazyhouse)
+    }
+
+    /// has_explosions() tests whether a capture blows up the pieces around
+    /// the capture square.
+    pub const fn has_explosions(self) -> bool {
+        matches!(self, Variant::Atomic)
+    }
+
+    /// has_checks() tests whether a king can be in check. Kings are not
+    /// royal in antichess.
+    pub const fn has_checks(self) -> bool {
+        !matches!(self, Variant::Antichess)
+    }
+
+    pub const fn has_castling(self) -> bool {
+        !matches!(self, Variant::Antichess)
+    }
+
+    /// king_can_be_lost() tests whether a side may be left without a king,
+    /// which then ends the game.
+    pub const fn king_can_be_lost(self) -> bool {
+        matches!(self, Variant::Atomic | Variant::Antichess)
+    }
+
+    /// king_is_piece() tests whether kings are ordinary pieces, which can
+    /// be captured and promoted to and of which a side may have several.
+    pub const fn king_is_piece(self) -> bool {
+        matches!(self, Variant::Antichess)
+    }
+
+    /// compulsory_captures() tests whether a capture must be made when one
+    /// is possible.
+    pub const fn compulsory_captures(self) -> bool {
+        matches!(self, Variant::Antichess)
+    }
+
+    /// has_own_legality() tests whether the legality of a move is decided
+    /// by rules of the variant, so that the shortcuts for pins and
+    /// evasions of standard chess do not apply.
+    pub const fn has_own_legality(self) -> bool {
+        matches!(self, Variant::Atomic | Variant::Antichess)
+    }
+
+    /// counts_checks() tests whether the checks given are counted, which is
+    /// part of the position and its FEN.
+    pub const fn counts_checks(self) -> bool {
+        matches!(self, Variant::ThreeCheck)
+    }
+
+    /// has_variant_end() tests whether the game can end by a rule other than
+    /// checkmate, stalemate or a draw.
+    pub const fn has_variant_end(self) -> bool {
+        matches!(self, Variant::Atomic | Variant::Antichess | Variant::ThreeCheck | Variant::KingOfTheHill)
+    }
+
+    /// has_exchanges() tests whether a capture wins the captured piece, as
+    /// static exchange evaluation and delta pruning assume. In atomic chess
+    /// the capturing piece explodes too, and in antichess losing material
+    /// is the goal.
+    pub const fn has_exchanges(self) -> bool {
+        !matches!(self, Variant::Atomic | Variant::Antichess)
+    }
+
+    /// stalemate_wins() tests whether a side without legal moves wins
+    pub const fn stalemate_wins(self) -> bool {
+        matches!(self, Variant::Antichess)
+    }
+
+    /// allows_null_move() tests whether null move pruning is sound enough,
+    /// which it is not when zugzwang is the rule.
+    pub const fn allows_null_move(self) -> bool {
+        !matches!(self, Variant::Antichess)
+    }
+}
+
+impl Position {
+    // variant_end() returns the value of the position for the side to move
+    // if the game has ended by a rule of the variant, e.g. the king was
+    // blown up in atomic chess, the third check was given in three-check or
+    // the king reached the center in King of the Hill. A side without legal
+    // moves in antichess wins, which is left to the search.
+
+    pub fn variant_end(&self, ply: i32) -> Option<Value> {
+        let us = self.side_to_move();
+        match self.variant() {
+            Variant::Atomic if self.pieces_cp(us, KING) == EMPTY_BB =>
+                Some(mated_in(ply)),
+            Variant::Antichess if self.pieces_c(us) == EMPTY_BB =>
+                Some(mate_in(ply)),
+            Variant::ThreeCheck if self.checks_given(!us) >= CHECKS_NB as i32 =>
+                Some(mated_in(ply)),
+            Variant::KingOfTheHill if self.pieces_cp(!us, KING) & CENTER != EMPTY_BB =>
+                Some(mated_in(ply)),
+            _ => None,
+        }
+    }
+
+    // variant_legal() tests whether a pseudo-legal move is legal in a
+    // variant with its own legality. Every pseudo-legal move is legal in
+    // antichess, where the move generator enforces the compulsory captures.
+
+    pub fn variant_legal(&self, m: Move) -> bool {
+        match self.variant() {
+            Variant::Atomic => self.atomic_legal(m),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod variant_test {
+
+    use super::*;
+    use crate::position::Position;
+    use crate::types::piece::*;
+    use crate::types::r#move::*;
+    use crate::types::square::Square;
+    use crate::types::bitboard::EMPTY_BB;
+
+    fn crazyhouse(fen: &str) -> Position {
+        let mut pos = Position::new();
+        pos.set_variant(Variant::Crazyhouse, fen, false);
+        pos
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Variant::from_name("Crazyhouse"), Some(Variant::Crazyhouse));
+        assert_eq!(Variant::from_name("chess"), Some(Variant::Chess));
+        assert_eq!(Variant::from_name("shogi"), None);
+        assert_eq!(Variant::from_name("King of the Hill"), Some(Variant::KingOfTheHill));
+        for v in Variant::ALL {
+            assert_eq!(Variant::from_name(v.name()), Some(v));
+            assert_eq!(Variant::from_name(v.pgn_name()), Some(v));
+        }
+        assert!(!Variant::Chess.has_drops());
+    }
+
+    #[test]
+    fn crazyhouse_fen() {
+        let fen = "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[Pnp] b KQkq - 0 1";
+        assert_eq!(crazyhouse(fen).fen(), fen);
+
+        // Pieces in hand may be given as a ninth rank
+        let pos = crazyhouse("4k3/1Q~6/8/8/4b3/8/Kpp5/8/Nq b - - 0 1");
+        assert_eq!(pos.fen(), "4k3/1Q~6/8/8/4b3/8/Kpp5/8[Nq] b - - 0 1");
+        assert!(pos.is_promoted(Square::B7));
+        assert_eq!(pos.count_in_hand(WHITE, KNIGHT), 1);
+        assert_eq!(pos.count_in_hand(BLACK, QUEEN), 1);
+
+        // Pockets are ignored in standard chess
+        let mut pos = Position::new();
+        pos.set("4k3/8/8/8/8/8/8/4K3[Q] w - - 0 1", false);
+        assert_eq!(pos.fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+
+    #[test]
+    fn crazyhouse_moves() {
+        let mut pos = crazyhouse("4k3/1Q~6/8/8/8/8/8/4K2n[] w - - 0 1");
+        pos.do_move(Move::from_uci(&pos, "b7h1").unwrap());
+        assert_eq!(pos.fen(), "4k3/8/8/8/8/8/8/4K2Q~[N] b - - 0 1");
+
+        // Capturing a promoted piece gives a pawn
+        let fen = "4k3/3Q~4/8/8/8/8/8/4K3[] b - - 0 1";
+        let mut pos = crazyhouse(fen);
+        let mut moves = Vec::new();
+        for m in ["e8d7", "e1e2", "P@d3", "e2d3"] {
+            let m = Move::from_uci(&pos, m).unwrap();
+            if m.move_type() == DROP {
+                assert_eq!(m, Move::make_drop(PAWN, Square::D3));
+                assert!(pos.gives_check(m));
+                assert_eq!(pos.move_to_san(m), "P@d3+");
+                assert_eq!(pos.parse_san("@d3"), m);
+            }
+            pos.do_move(m);
+            moves.push(m);
+
+            // The hash key accounts for the pieces in hand
+            assert_eq!(crazyhouse(&pos.fen()).key(), pos.key());
+        }
+        assert_eq!(pos.fen(), "8/3k4/8/8/8/3K4/8/8[P] b - - 0 3");
+
+        for &m in moves.iter().rev() {
+            pos.undo_move(m);
+        }
+        assert_eq!(pos.fen(), fen);
+
+        // Only drops blocking the check are legal evasions
+        let pos = crazyhouse("4k3/8/8/8/8/8/8/r3K3[Nn] w - - 0 1");
+        assert_eq!(pos.legal_moves().iter().filter(|m| m.move_type() == DROP).count(), 3);
+    }
+
+    #[test]
+    fn antichess_moves() {
+        let mut pos = Position::new();
+
+        // Pawns can promote to a king, which does not fit in a TT move
+        pos.set_variant(Variant::Antichess, "8/P7/8/8/8/8/8/7k w - - 0 1", false);
+        assert_eq!(pos.legal_moves().len(), 5);
+        let m = Move::from_uci(&pos, "a7a8k").unwrap();
+        assert_eq!(m.promotion_type(), KING);
+        assert_eq!(pos.move_to_san(m), "a8=K");
+        assert_eq!(pos.parse_san("a8=K"), m);
+        assert_eq!(Move::from_u16(m.to_u16()), Move::NONE);
+
+        // Captures are compulsory, also for and of the king, and there is
+        // no check.
+        pos.set_variant(Variant::Antichess, "8/8/8/8/8/8/1k6/K6r w - - 0 1", false);
+        assert_eq!(pos.checkers(), EMPTY_BB);
+        let moves = pos.legal_moves();
+        assert_eq!(moves.len(), 1);
+        pos.do_move(moves[0]);
+        assert_eq!(pos.fen(), "8/8/8/8/8/8/1K6/7r b - - 0 1");
+        assert!(pos.legal_moves().iter().all(|m| m.from() == Square::H1));
+    }
+
+    #[test]
+    fn three_check() {
+        let mut pos = Position::new();
+        pos.set_variant(Variant::ThreeCheck, "rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3 +1+0", false);
+        assert_eq!(pos.checks_given(WHITE), 1);
+
+        // Remaining checks are converted to checks given
+        let mut other = Position::new();
+        other.set_variant(Variant::ThreeCheck, "rnbqkbnr/ppp2ppp/8/3pp3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 2+3 0 3", false);
+        assert_eq!(other.fen(), pos.fen());
+        assert_eq!(other.key(), pos.key());
+
+        // The checks given are part of the hash key
+        for uci in ["f1b5", "c7c6", "b5c6"] {
+            let m = Move::from_uci(&pos, uci).unwrap();
+            pos.do_move(m);
+            let mut copy = Position::new();
+            copy.set_variant(Variant::ThreeCheck, &pos.fen(), false);
+            assert_eq!(copy.key(), pos.key());
+        }
+        assert_eq!(pos.fen(), "rnbqkbnr/pp3ppp/2B5/3pp3/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 0 4 +3+0");
+        assert!(pos.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        let mut pos = Position::new();
+        pos.set_variant(Variant::KingOfTheHill, "4k3/8/8/8/8/4K3/8/8 w - - 0 1", false);
+        pos.do_move(Move::from_uci(&pos, "e3e4").unwrap());
+        assert!(pos.legal_moves().is_empty());
+        assert!(pos.variant_end(0).is_some());
+    }
+
+    #[test]
+    fn drops_in_tt_move() {
+        for pt in [PAWN, KNIGHT, BISHOP, ROOK, QUEEN] {
+            let m = Move::make_drop(pt, Square::C1);
+            assert_eq!(Move::from_u16(m.to_u16()), m);
+            assert_eq!(m.to_string(false), format!("{}@c1", PIECE_TO_CHAR[pt.0 as usize]));
+        }
+        let m = Move::make_prom(Square::B7, Square::A8, QUEEN);
+        assert_eq!(Move::from_u16(m.to_u16()), m);
+        assert_eq!(Move::from_u16(Move::NULL.to_u16()), Move::NULL);
+    }
+}
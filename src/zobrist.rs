@@ -1,11 +1,21 @@
-use crate::types::square::{SQUARE_NB, FILE_NB, VALID_SQUARES, VALID_FILES};
-use crate::types::piece::{PIECE_NB, VALID_PIECES};
-use crate::types::r#move::{CASTLING_RIGHT_NB, VALID_CASTLING_RIGHTS};
+use crate::types::square::{SQUARE_NB, FILE_NB};
+use crate::types::piece::{PIECE_NB, COLOR_NB, VALID_PIECES};
+use crate::types::r#move::CASTLING_RIGHT_NB;
 use crate::rng;
 
 pub type Key = u64;
 pub const KEY_ZERO: Key = 0u64; 
 
+// Maximum number of pieces of a kind in hand, plus one: all the 16 pawns
+pub const HAND_NB: usize = 17;
+
+// Checks that can be given in three-check before the game is over
+pub const CHECKS_NB: usize = 3;
+
+// The Zobrist keys are the same for every position, so they are generated
+// once at compile time instead of being stored in each Position.
+pub static ZOBRIST: Zobrist = Zobrist::new();
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Zobrist {
     pub psq: [[Key; SQUARE_NB]; PIECE_NB],
@@ -13,40 +23,113 @@ pub struct Zobrist {
     pub castling: [Key; CASTLING_RIGHT_NB],
     pub side: Key,
     pub no_pawns: Key,
+    pub in_hand: [[Key; HAND_NB]; PIECE_NB],
+    pub checks: [[Key; CHECKS_NB]; COLOR_NB],
 }
 
 impl Zobrist {
 
-    pub fn new() -> Zobrist {
-        Zobrist {
+    // new() generates the keys with the same PRNG seed and in the same order
+    // as the runtime initialization they replace, so the keys are unchanged.
+    // Only the valid pieces get keys; loops are used because iterators are
+    // not available in a const fn.
+    const fn new() -> Zobrist {
+        let mut z = Zobrist {
             psq : [[KEY_ZERO; SQUARE_NB]; PIECE_NB],
             en_passant : [KEY_ZERO; FILE_NB],
             castling : [KEY_ZERO; CASTLING_RIGHT_NB],
             side : KEY_ZERO,
             no_pawns : KEY_ZERO,
+            in_hand : [[KEY_ZERO; HAND_NB]; PIECE_NB],
+            checks : [[KEY_ZERO; CHECKS_NB]; COLOR_NB],
+        };
+
+        let mut rng = rng::Prng::new(1070372);
+
+        let mut i = 0;
+        while i < VALID_PIECES.len() {
+            let pc = VALID_PIECES[i].0 as usize;
+            let mut s = 0;
+            while s < SQUARE_NB {
+                z.psq[pc][s] = rng.rand64();
+                s += 1;
+            }
+            i += 1;
+        }
+
+        let mut f = 0;
+        while f < FILE_NB {
+            z.en_passant[f] = rng.rand64();
+            f += 1;
+        }
+
+        let mut cr = 0;
+        while cr < CASTLING_RIGHT_NB {
+            z.castling[cr] = rng.rand64();
+            cr += 1;
+        }
+
+        z.side = rng.rand64();
+        z.no_pawns = rng.rand64();
+
+        let mut i = 0;
+        while i < VALID_PIECES.len() {
+            let pc = VALID_PIECES[i].0 as usize;
+            let mut n = 0;
+            while n < HAND_NB {
+                z.in_hand[pc][n] = rng.rand64();
+                n += 1;
+            }
+            i += 1;
         }
+
+        let mut c = 0;
+        while c < COLOR_NB {
+            let mut n = 0;
+            while n < CHECKS_NB {
+                z.checks[c][n] = rng.rand64();
+                n += 1;
+            }
+            c += 1;
+        }
+
+        z
     }
+}
+
+#[cfg(test)]
+mod zobrist_test {
 
-    pub fn init(& mut self) {
+    use super::*;
+    use crate::types::square::{VALID_SQUARES, VALID_FILES};
+    use crate::types::r#move::VALID_CASTLING_RIGHTS;
 
+    #[test]
+    fn compile_time_keys() {
         let mut rng = rng::Prng::new(1070372);
 
         for pc in VALID_PIECES {
             for s in VALID_SQUARES {
-                self.psq[pc][s] = rng.rand::<Key>();
+                assert_eq!(ZOBRIST.psq[pc][s], rng.rand::<Key>());
             }
         }
-
         for f in VALID_FILES {
-            self.en_passant[f] = rng.rand::<Key>();
+            assert_eq!(ZOBRIST.en_passant[f], rng.rand::<Key>());
         }
-
         for cr in VALID_CASTLING_RIGHTS {
-            self.castling[cr] = rng.rand::<Key>();
+            assert_eq!(ZOBRIST.castling[cr], rng.rand::<Key>());
+        }
+        assert_eq!(ZOBRIST.side, rng.rand::<Key>());
+        assert_eq!(ZOBRIST.no_pawns, rng.rand::<Key>());
+        for pc in VALID_PIECES {
+            for n in 0..HAND_NB {
+                assert_eq!(ZOBRIST.in_hand[pc][n], rng.rand::<Key>());
+            }
+        }
+        for c in 0..COLOR_NB {
+            for n in 0..CHECKS_NB {
+                assert_eq!(ZOBRIST.checks[c][n], rng.rand::<Key>());
+            }
         }
-        
-        self.side = rng.rand::<Key>();
-        self.no_pawns = rng.rand::<Key>();
-
     }
 }